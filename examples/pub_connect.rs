@@ -19,10 +19,10 @@ fn main() -> io::Result<()> {
     eprintln!("Connected PUB socket to {:?}", address);
 
     for i in (0..100).step_by(33) {
-        pubs.send(vec![vec![i, i << 1, i & 1]]).unwrap();
+        pubs.send([[i, i << 1, i & 1]]).unwrap();
     }
 
-    pubs.send(vec![vec![69, 4, 20]]).unwrap();
+    pubs.send([[69, 4, 20]]).unwrap();
 
     for _ in 0..5 {
         let _ = dbg!(sub.recv_multipart(0x00)).unwrap();
@@ -20,9 +20,9 @@ fn main() -> io::Result<()> {
 
     eprintln!("Subscribed with empty prefix");
 
-
     thread::spawn(move || loop {
-        pubs.send_multipart(vec![vec![0xDE, 0xAD, 0xBE, 0xEF]], 0x00).unwrap();
+        pubs.send_multipart(vec![vec![0xDE, 0xAD, 0xBE, 0xEF]], 0x00)
+            .unwrap();
         eprintln!("Tick.");
         thread::sleep(Duration::from_millis(333));
     });
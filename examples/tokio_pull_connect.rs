@@ -0,0 +1,14 @@
+use std::io;
+
+use zedmq::aio::Pull;
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let mut socket: Pull = zedmq::aio::connect("tcp", "127.0.0.1:5678").await?;
+
+    while let Ok(message) = socket.recv().await {
+        dbg!(message);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Asynchronous sockets built on top of [tokio](https://tokio.rs).
+//!
+//! Every socket type here mirrors its blocking counterpart in the crate
+//! root, `send` and `recv` simply need to be `.await`ed. The `Req`/`Rep`
+//! typestate design is kept as-is so `aio::Req::send` consumes the socket
+//! and hands back an `aio::ReqPending`.
+//!
+//! ```rust,no_run
+//! use zedmq::aio::Pull;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let mut socket: Pull = zedmq::aio::connect("tcp", "127.0.0.1:5678").await?;
+//!
+//! while let Ok(message) = socket.recv().await {
+//!     dbg!(message);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::io;
+
+use crate::{SocketOptions, SocketType, TransportKind};
+
+mod pub_t;
+mod pull_t;
+mod push_t;
+mod rep_t;
+mod req_t;
+mod stream;
+mod sub_t;
+
+pub use self::{
+    pub_t::Pub,
+    pull_t::Pull,
+    push_t::Push,
+    rep_t::{Rep, RepPending},
+    req_t::{Req, ReqPending},
+    stream::Stream,
+    sub_t::Sub,
+};
+
+crate::socket_type::impl_options![Pull, Push, Sub, Pub, Req, ReqPending, Rep, RepPending];
+
+/// Give socket types an async `close` method that lingers on queued messages.
+macro_rules! impl_close {
+    [$( $name:ident ),+] => {
+        $(
+            impl $name {
+                /// Flush queued messages for up to the linger period and close the connection.
+                ///
+                /// Dropping the socket does the same in the background, on the
+                /// current runtime, `close` gets to wait on it and report errors.
+                pub async fn close(mut self) -> io::Result<()> {
+                    self.inner.close().await
+                }
+            }
+        )+
+    };
+}
+
+impl_close![Pull, Push, Sub, Pub, Req, ReqPending, Rep, RepPending];
+
+/// Start an asynchronous ZMQ socket with the specified `transport` to the specified `address`.
+///
+/// Both `"tcp"` and (on unix platforms) `"ipc"` transports are supported,
+/// unknown ones fail with `InvalidInput`.
+pub async fn connect<S, T>(transport: T, address: &str) -> io::Result<S>
+where
+    S: SocketType + From<Stream>,
+    T: TryInto<TransportKind>,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    connect_with(transport, address, SocketOptions::default()).await
+}
+
+/// Like [`connect`] but with the socket configured by `options`.
+///
+/// `options` may also just be a [`ReconnectPolicy`](crate::ReconnectPolicy).
+/// The send and receive timeouts apply to every `send` and `recv` call.
+pub async fn connect_with<S, T, O>(transport: T, address: &str, options: O) -> io::Result<S>
+where
+    S: SocketType + From<Stream>,
+    T: TryInto<TransportKind>,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    O: Into<SocketOptions>,
+{
+    let name = <S as SocketType>::name();
+    let transport = crate::transport_kind(transport)?;
+    let stream = Stream::connected(name, transport, address, options.into()).await?;
+
+    Ok(stream.into())
+}
@@ -0,0 +1,29 @@
+use std::io;
+
+use super::Stream;
+use crate::MessageBuf;
+
+/// An asynchronous zmq PUB socket.
+#[derive(Debug)]
+pub struct Pub {
+    pub(super) inner: Stream,
+}
+
+impl From<Stream> for Pub {
+    fn from(inner: Stream) -> Self {
+        Self { inner }
+    }
+}
+
+impl Pub {
+    /// Send a message.
+    ///
+    /// We're on the connected end of the session so we rely on the
+    /// subscriber in order to perform filtering.
+    pub async fn send<M>(&mut self, message: M) -> io::Result<()>
+    where
+        M: Into<MessageBuf>,
+    {
+        self.inner.send(message.into().as_message()).await
+    }
+}
@@ -0,0 +1,28 @@
+use std::io;
+
+use super::Stream;
+use crate::{Message, MessageBuf};
+
+/// An asynchronous zmq PULL socket.
+#[derive(Debug)]
+pub struct Pull {
+    pub(super) inner: Stream,
+}
+
+impl From<Stream> for Pull {
+    fn from(inner: Stream) -> Self {
+        Self { inner }
+    }
+}
+
+impl Pull {
+    /// Receive a multi-part message.
+    pub async fn recv(&mut self) -> io::Result<MessageBuf> {
+        self.inner.recv().await
+    }
+
+    /// Receive a multi-part message into `message`, reusing its storage.
+    pub async fn recv_into<'b>(&mut self, message: &'b mut MessageBuf) -> io::Result<Message<'b>> {
+        self.inner.recv_into(message, &mut ()).await
+    }
+}
@@ -0,0 +1,26 @@
+use std::io;
+
+use super::Stream;
+use crate::MessageBuf;
+
+/// An asynchronous zmq PUSH socket.
+#[derive(Debug)]
+pub struct Push {
+    pub(super) inner: Stream,
+}
+
+impl From<Stream> for Push {
+    fn from(inner: Stream) -> Self {
+        Self { inner }
+    }
+}
+
+impl Push {
+    /// Send a message.
+    pub async fn send<M>(&mut self, message: M) -> io::Result<()>
+    where
+        M: Into<MessageBuf>,
+    {
+        self.inner.send(message.into().as_message()).await
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+
+use super::Stream;
+use crate::MessageBuf;
+
+// -- RepPending
+
+/// An asynchronous zmq REP socket pending a response.
+#[derive(Debug)]
+pub struct RepPending {
+    pub(super) inner: Stream,
+}
+
+impl RepPending {
+    /// Send a message.
+    pub async fn send<M>(mut self, message: M) -> io::Result<Rep>
+    where
+        M: Into<MessageBuf>,
+    {
+        self.inner.send(message.into().as_message()).await?;
+        Ok(Rep { inner: self.inner })
+    }
+}
+
+// -- Rep
+
+/// An asynchronous zmq REP socket.
+#[derive(Debug)]
+pub struct Rep {
+    pub(super) inner: Stream,
+}
+
+impl From<Stream> for Rep {
+    fn from(inner: Stream) -> Self {
+        Self { inner }
+    }
+}
+
+impl Rep {
+    /// Recieve a multipart message with the pending REP socket.
+    pub async fn recv(mut self) -> io::Result<(MessageBuf, RepPending)> {
+        let data = self.inner.recv().await?;
+        let Self { inner } = self;
+        Ok((data, RepPending { inner }))
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+
+use super::Stream;
+use crate::MessageBuf;
+
+// -- ReqPending
+
+/// An asynchronous zmq REQ socket pending a response.
+#[derive(Debug)]
+pub struct ReqPending {
+    pub(super) inner: Stream,
+}
+
+impl ReqPending {
+    /// recieve a message.
+    pub async fn recv(mut self) -> io::Result<(MessageBuf, Req)> {
+        let data = self.inner.recv().await?;
+        let Self { inner } = self;
+        Ok((data, Req { inner }))
+    }
+}
+
+// -- Req
+
+impl From<Stream> for Req {
+    fn from(inner: Stream) -> Self {
+        Self { inner }
+    }
+}
+
+/// An asynchronous zmq REQ socket.
+#[derive(Debug)]
+pub struct Req {
+    pub(super) inner: Stream,
+}
+
+impl Req {
+    /// Send a message.
+    pub async fn send<M>(mut self, message: M) -> io::Result<ReqPending>
+    where
+        M: Into<MessageBuf>,
+    {
+        self.inner.send(message.into().as_message()).await?;
+        Ok(ReqPending { inner: self.inner })
+    }
+}
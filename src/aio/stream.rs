@@ -0,0 +1,599 @@
+use std::future::Future;
+use std::io::{self, IoSlice};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+};
+
+use crate::{
+    codec::{Encoder, Engine, Event, Message, MessageBuf, READ_CHUNK},
+    reconnect::Failure,
+    socket_type::Handler,
+    stream::{ConnectHook, HANG_UP_WAIT, MAX_DRAIN},
+    SocketOptions, TransportKind,
+};
+
+// -- Transport
+
+#[derive(Debug)]
+pub(crate) enum Transport {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Ipc(UnixStream),
+}
+
+impl Transport {
+    /// Write what the socket takes right now without waiting for it to become writable.
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.try_write(buf),
+            #[cfg(unix)]
+            Self::Ipc(stream) => stream.try_write(buf),
+        }
+    }
+
+    /// Read what the socket has right now without waiting for it to become readable.
+    fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.try_read(buf),
+            #[cfg(unix)]
+            Self::Ipc(stream) => stream.try_read(buf),
+        }
+    }
+
+    /// Like `try_write` but for several buffers at once.
+    fn try_write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.try_write_vectored(bufs),
+            #[cfg(unix)]
+            Self::Ipc(stream) => stream.try_write_vectored(bufs),
+        }
+    }
+}
+
+/// Write `message` straight from its buffer with vectored writes, `engine` may not have anything queued up.
+///
+/// Whatever the peer doesn't take right away is queued up in the engine.
+fn send_vectored(
+    transport: &Transport,
+    engine: &mut Engine,
+    encoder: &mut Encoder,
+    message: Message<'_>,
+) -> io::Result<()> {
+    let mut slices = encoder.encode(message)?;
+    let mut remainder = &mut slices[..];
+
+    while !remainder.is_empty() {
+        match transport.try_write_vectored(remainder) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(n) => IoSlice::advance_slices(&mut remainder, n),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    engine.write_remainder(remainder);
+
+    Ok(())
+}
+
+impl AsyncRead for Transport {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Ipc(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Transport {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Ipc(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Ipc(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Self::Ipc(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Write out everything `engine` has queued up for the peer.
+///
+/// Output is consumed after every write, if this gets cancelled part way
+/// (timed out, dropped in a `select!`...) what the peer already got is
+/// never sent again.
+async fn write_output(transport: &mut Transport, engine: &mut Engine) -> io::Result<()> {
+    while !engine.output().is_empty() {
+        match transport.write(engine.output()).await? {
+            0 => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            n => engine.consume_output(n),
+        }
+    }
+
+    Ok(())
+}
+
+/// Drive `engine` through the ZMTP handshake over `transport`.
+async fn handshake(transport: &mut Transport, engine: &mut Engine) -> io::Result<()> {
+    loop {
+        write_output(transport, engine).await?;
+
+        if engine.is_ready() {
+            return Ok(());
+        }
+
+        let event = match engine.poll() {
+            Ok(event) => event,
+            Err(err) => {
+                // An ERROR may have been queued up to tell the peer why it's turned away.
+                let _ = write_output(transport, engine).await;
+                return Err(err);
+            }
+        };
+
+        // Only wait on the peer once everything it needs from us has been sent.
+        if event.is_none() && engine.output().is_empty() {
+            match transport.read(engine.read_buf(engine.read_size())).await? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                n => engine.commit(n),
+            }
+        }
+    }
+}
+
+// -- Stream
+
+/// The asynchronous counterpart of the blocking connection primitive.
+#[derive(Debug)]
+pub struct Stream {
+    socket_type: &'static str,
+    kind: TransportKind,
+    address: String,
+    options: SocketOptions,
+    transport: Option<Transport>,
+    engine: Option<Engine>,
+    encoder: Encoder,
+    on_connect: Option<ConnectHook>,
+}
+
+impl Stream {
+    /// Given an `address` produce a `Stream` that is connected even if connecting may take a while.
+    pub(crate) async fn connected(
+        socket_type: &'static str,
+        kind: TransportKind,
+        address: &str,
+        options: SocketOptions,
+    ) -> io::Result<Self> {
+        options.check()?;
+
+        let mut stream = Self {
+            socket_type,
+            kind,
+            address: address.to_string(),
+            options,
+            transport: None,
+            engine: None,
+            encoder: Encoder::default(),
+            on_connect: None,
+        };
+
+        stream.ensure_connected().await?;
+
+        Ok(stream)
+    }
+
+    /// Open a fresh transport to the endpoint.
+    async fn open(&self) -> Result<Transport, (Failure, io::Error)> {
+        let classify = |err: io::Error| (Failure::of_connect(&err), err);
+
+        match self.kind {
+            TransportKind::TCP => {
+                let addrs: Vec<SocketAddr> = tokio::net::lookup_host(&self.address)
+                    .await
+                    .map_err(|err| (Failure::Refused, err))?
+                    .collect();
+
+                let stream = TcpStream::connect(&addrs[..]).await.map_err(classify)?;
+
+                Ok(Transport::Tcp(stream))
+            }
+
+            #[cfg(unix)]
+            TransportKind::IPC => Ok(Transport::Ipc(
+                UnixStream::connect(&self.address).await.map_err(classify)?,
+            )),
+
+            #[cfg(not(unix))]
+            TransportKind::IPC => Err((
+                Failure::Refused,
+                io::Error::from(io::ErrorKind::Unsupported),
+            )),
+        }
+    }
+
+    /// Open a fresh transport and perform the handshake on it.
+    async fn connect(&self) -> Result<(Transport, Engine), (Failure, io::Error)> {
+        let mut transport = self.open().await?;
+        let mut engine = Engine::new(self.socket_type, &self.options);
+
+        handshake(&mut transport, &mut engine)
+            .await
+            .map_err(|err| (Failure::Handshake, err))?;
+
+        Ok((transport, engine))
+    }
+
+    /// The options this stream was configured with.
+    #[inline]
+    pub(crate) fn options(&self) -> &SocketOptions {
+        &self.options
+    }
+
+    /// Have `hook` called on every connection made from now on, e.g. to replay subscriptions.
+    pub(crate) fn on_connect<F>(&mut self, hook: F)
+    where
+        F: FnMut(&mut Engine) -> io::Result<()> + Send + 'static,
+    {
+        self.on_connect = Some(ConnectHook::new(hook));
+    }
+
+    /// Forget about the current connection, the next use will reconnect.
+    fn disconnect(&mut self) {
+        self.transport.take();
+        self.engine.take();
+    }
+
+    /// Get the current transport, (re)connecting according to the reconnect policy if needed.
+    ///
+    /// Once the policy gives up the error of the last attempt is returned.
+    pub(crate) async fn ensure_connected(&mut self) -> io::Result<&mut Transport> {
+        let mut attempt = 0;
+
+        while self.transport.is_none() {
+            match self.connect().await {
+                Ok((fresh, mut engine)) => {
+                    if let Some(hook) = self.on_connect.as_mut() {
+                        hook.call(&mut engine)?;
+                    }
+
+                    let _ = self.transport.replace(fresh);
+                    let _ = self.engine.replace(engine);
+                    break;
+                }
+
+                Err((failure, err)) => {
+                    attempt += 1;
+
+                    let reconnect = self.options.reconnect();
+
+                    if !reconnect.should_retry(attempt, failure) {
+                        return Err(reconnect.give_up(attempt, err));
+                    }
+
+                    tokio::time::sleep(reconnect.delay(attempt)).await;
+                }
+            }
+        }
+
+        match self.transport.as_mut() {
+            Some(inner) => Ok(inner),
+            None => unreachable!(),
+        }
+    }
+
+    /// Read whatever the peer sent so far into the engine without waiting, so the latest of it can be taken.
+    ///
+    /// A peer that sends faster than it can be drained gets cut off after `MAX_DRAIN` bytes.
+    fn drain(&mut self) -> io::Result<()> {
+        if let (Some(transport), Some(engine)) = (self.transport.as_ref(), self.engine.as_mut()) {
+            let mut drained = 0;
+
+            while drained < MAX_DRAIN {
+                let read = transport.try_read(engine.read_buf(READ_CHUNK));
+                engine.commit(*read.as_ref().unwrap_or(&0));
+
+                match read {
+                    // The next read finds out that the peer hung up.
+                    Ok(0) => break,
+                    Ok(n) => drained += n,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write out as much of the engine's queued output as the peer takes without waiting.
+    fn try_flush_output(&mut self) -> io::Result<()> {
+        if let (Some(transport), Some(engine)) = (self.transport.as_ref(), self.engine.as_mut()) {
+            while !engine.output().is_empty() {
+                match transport.try_write(engine.output()) {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                    Ok(n) => engine.consume_output(n),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flush queued up messages for up to the linger period and hang up.
+    ///
+    /// This never reconnects, if there's no connection there's nothing to
+    /// flush. Fails with `TimedOut` if the linger period expired with
+    /// messages still queued, those are discarded.
+    pub(crate) async fn close(&mut self) -> io::Result<()> {
+        match (self.transport.take(), self.engine.take()) {
+            (Some(transport), Some(engine)) => {
+                close(transport, engine, self.options.linger()).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write out everything the engine has queued up for the peer.
+    async fn flush_output(&mut self) -> io::Result<()> {
+        self.ensure_connected().await?;
+
+        match (self.transport.as_mut(), self.engine.as_mut()) {
+            (Some(transport), Some(engine)) => write_output(transport, engine).await,
+
+            _ => unreachable!(),
+        }
+    }
+
+    /// Subscribe the peer to `topic`, or with `subscribe` unset cancel that subscription.
+    pub(crate) async fn write_subscription(
+        &mut self,
+        topic: &[u8],
+        subscribe: bool,
+    ) -> io::Result<()> {
+        self.ensure_connected().await?;
+
+        if let Some(engine) = self.engine.as_mut() {
+            engine.write_subscription(topic, subscribe)?;
+        }
+
+        self.flush_output().await
+    }
+
+    /// Queue up a multi-part message for the peer and write out what we can without waiting.
+    ///
+    /// Once the SNDHWM is reached this waits until the peer catches up, or
+    /// drops the message, depending on the socket type. Fails with `TimedOut`
+    /// if this takes longer than the send timeout.
+    pub(crate) async fn send(&mut self, message: Message<'_>) -> io::Result<()> {
+        let timeout = self.options.sndtimeo();
+
+        within(timeout, async {
+            self.ensure_connected().await?;
+
+            if let (Some(transport), Some(engine)) = (self.transport.as_ref(), self.engine.as_mut())
+            {
+                if engine.output().is_empty() {
+                    return send_vectored(transport, engine, &mut self.encoder, message);
+                }
+            }
+
+            loop {
+                let queued = match self.engine.as_mut() {
+                    Some(engine) => engine.write_message(message),
+                    None => unreachable!(),
+                };
+
+                match queued {
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        self.flush_output().await?
+                    }
+                    queued => break queued?,
+                }
+            }
+
+            self.try_flush_output()
+        })
+        .await
+    }
+
+    /// Receive a multi-part message.
+    ///
+    /// Fails with `TimedOut` if this takes longer than the receive timeout.
+    pub(crate) async fn recv(&mut self) -> io::Result<MessageBuf> {
+        let timeout = self.options.rcvtimeo();
+
+        let mut message = MessageBuf::new();
+
+        within(timeout, self.recv_message(&mut message, &mut ())).await?;
+
+        Ok(message)
+    }
+
+    /// Receive a multi-part message into `message`, reusing its storage.
+    ///
+    /// Fails with `TimedOut` if this takes longer than the receive timeout.
+    /// Commands that come in between messages are passed on to `handler`.
+    pub(crate) async fn recv_into<'b>(
+        &mut self,
+        message: &'b mut MessageBuf,
+        handler: &mut (dyn Handler + Send),
+    ) -> io::Result<Message<'b>> {
+        let timeout = self.options.rcvtimeo();
+
+        within(timeout, self.recv_message(message, handler)).await?;
+
+        Ok(message.as_message())
+    }
+
+    /// If the peer hangs up we reconnect and start over with a fresh message.
+    async fn recv_message(
+        &mut self,
+        message: &mut MessageBuf,
+        handler: &mut (dyn Handler + Send),
+    ) -> io::Result<()> {
+        loop {
+            self.ensure_connected().await?;
+
+            if self.engine.as_ref().is_some_and(Engine::conflates) {
+                self.drain()?;
+            }
+
+            let accept = &mut |head: &[u8]| handler.accepts(head);
+
+            match self.engine.as_mut() {
+                Some(engine) => match engine.poll_into_filtered(message, accept) {
+                    Ok(Some(Event::Message)) => break,
+                    Ok(Some(Event::Command(command))) => {
+                        handler.command(command)?;
+                        continue;
+                    }
+                    Ok(_) => (),
+
+                    // There's no telling where the next frame starts after the peer broke protocol.
+                    Err(err) => {
+                        self.disconnect();
+                        return Err(err);
+                    }
+                },
+                None => unreachable!(),
+            }
+
+            // Whatever is still queued up may be what the peer is waiting on.
+            self.flush_output().await?;
+
+            let n = match (self.transport.as_mut(), self.engine.as_mut()) {
+                (Some(transport), Some(engine)) => {
+                    let n = transport.read(engine.read_buf(engine.read_size())).await?;
+                    engine.commit(n);
+                    n
+                }
+
+                _ => unreachable!(),
+            };
+
+            if n == 0 {
+                self.disconnect();
+            }
+        }
+
+        // Answers to PINGs go out now rather than with the next read.
+        if self
+            .engine
+            .as_ref()
+            .is_some_and(|engine| !engine.output().is_empty())
+        {
+            self.flush_output().await?;
+        }
+
+        message.set_metadata(
+            self.engine
+                .as_ref()
+                .and_then(|engine| engine.peer().cloned()),
+        );
+
+        Ok(())
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let linger = self.options.linger();
+
+        // We can't wait around here, so linger in the background if there's a runtime to do so.
+        if let (Some(transport), Some(engine)) = (self.transport.take(), self.engine.take()) {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(close(transport, engine, linger));
+                }
+
+                Err(_) => {
+                    self.transport = Some(transport);
+                    self.engine = Some(engine);
+                    let _ = self.try_flush_output();
+                }
+            }
+        }
+    }
+}
+
+/// Flush `engine`'s queued output for up to `linger` and hang up.
+///
+/// Our side is then shut down, if anything was queued up we also wait a
+/// little (no longer than `HANG_UP_WAIT` or `linger`) for the peer to hang
+/// up so that nothing still in flight gets reset.
+async fn close(
+    mut transport: Transport,
+    mut engine: Engine,
+    linger: Option<Duration>,
+) -> io::Result<()> {
+    let deadline = linger.map(|linger| tokio::time::Instant::now() + linger);
+    let queued = !engine.output().is_empty();
+
+    let flushed = within(linger, write_output(&mut transport, &mut engine)).await;
+
+    let remaining = deadline
+        .map_or(HANG_UP_WAIT, |deadline| {
+            deadline.saturating_duration_since(tokio::time::Instant::now())
+        })
+        .min(HANG_UP_WAIT);
+
+    let _ = within(Some(remaining), async {
+        transport.shutdown().await?;
+
+        let mut scratch = [0u8; 512];
+
+        while queued && transport.read(&mut scratch).await? != 0 {}
+
+        Ok(())
+    })
+    .await;
+
+    flushed
+}
+
+/// Run `fut` to completion, giving up with `TimedOut` after `timeout` if there is one.
+async fn within<F, T>(timeout: Option<Duration>, fut: F) -> io::Result<T>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    match timeout {
+        None => fut.await,
+        Some(timeout) => tokio::time::timeout(timeout, fut)
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use super::Stream;
+use crate::socket_type::{
+    sub_t::{self, Filters, Matching},
+    trie::Trie,
+};
+use crate::{Message, MessageBuf};
+
+/// An asynchronous ZMQ SUB socket.
+#[derive(Debug)]
+pub struct Sub {
+    pub(super) inner: Stream,
+    topics: Arc<Mutex<Trie>>,
+    filters: Filters,
+}
+
+impl From<Stream> for Sub {
+    fn from(mut inner: Stream) -> Self {
+        // Every fresh connection is told about the subscriptions so far.
+        let topics = Arc::<Mutex<Trie>>::default();
+        let replayed = topics.clone();
+
+        inner.on_connect(move |engine| sub_t::replay(&sub_t::lock(&replayed), engine));
+
+        let filters = Filters::new(inner.options().invert_matching());
+
+        Self {
+            inner,
+            topics,
+            filters,
+        }
+    }
+}
+
+impl Sub {
+    /// Subscribe to a topic.
+    ///
+    /// Subscribing to a topic more than once only tells the publisher the first time, and takes as
+    /// many `unsubscribe`s to undo.
+    pub async fn subscribe(&mut self, topic: &[u8]) -> io::Result<()> {
+        // A fresh connection is told about every topic as it's made, connect
+        // first so that the topic isn't told about twice.
+        self.inner.ensure_connected().await?;
+
+        // Note down the subscribing topic locally for prefix matching when receiving.
+        let added = sub_t::lock(&self.topics).add(topic);
+
+        match added {
+            true => self.inner.write_subscription(topic, true).await,
+            false => Ok(()),
+        }
+    }
+
+    /// Unsubscribe from a topic, undoing one `subscribe` to it.
+    ///
+    /// Topics that were never subscribed to are ignored.
+    pub async fn unsubscribe(&mut self, topic: &[u8]) -> io::Result<()> {
+        self.inner.ensure_connected().await?;
+
+        let forgotten = sub_t::lock(&self.topics).remove(topic);
+
+        match forgotten {
+            true => self.inner.write_subscription(topic, false).await,
+            false => Ok(()),
+        }
+    }
+
+    /// Only take messages whose first frame satisfies `predicate`, on top of matching a subscription.
+    ///
+    /// See [`Sub::filter`](crate::Sub::filter) for details.
+    pub fn filter<F>(&mut self, predicate: F)
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.filters.push(predicate);
+    }
+
+    /// Recieve a message that matches a subscribed topic prefix and the filters.
+    pub async fn recv(&mut self) -> io::Result<MessageBuf> {
+        let mut message = MessageBuf::new();
+        self.recv_into(&mut message).await?;
+        Ok(message)
+    }
+
+    /// Recieve a matching message into `message`, reusing its storage.
+    ///
+    /// Messages that don't are dropped without being copied into `message`.
+    pub async fn recv_into<'b>(&mut self, message: &'b mut MessageBuf) -> io::Result<Message<'b>> {
+        let topics = &self.topics;
+
+        let mut matching = Matching {
+            subscribed: &|head| sub_t::lock(topics).matches(head),
+            filters: &self.filters,
+        };
+
+        self.inner.recv_into(message, &mut matching).await
+    }
+
+    /// Receive a multipart message without performing prefix checks.
+    pub async fn recv_unchecked(&mut self) -> io::Result<MessageBuf> {
+        self.inner.recv().await
+    }
+}
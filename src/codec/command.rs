@@ -1,135 +1,455 @@
-use std::fmt;
+use std::{
+    convert::{TryFrom, TryInto},
+    io,
+};
 
-use super::Frame;
+use super::{encode_header, FrameBuf, FrameKind, Metadata};
 
-// -- PropertyIterator<'a>
+/// The most context a PING or PONG may carry.
+pub const MAX_PING_CONTEXT: usize = 16;
 
-struct PropertyIterator<'a, I>
-where
-    I: Iterator<Item = (usize, &'a u8)>,
-{
-    inner: &'a Command<'a>,
-    cursor: I,
+#[inline]
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
 }
 
-impl<'a, I> Iterator for PropertyIterator<'a, I>
-where
-    I: Iterator<Item = (usize, &'a u8)>,
-{
-    type Item = (&'a str, &'a str);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let (name_idx, name_size) = self.cursor.next()?;
-
-        // Jump over the name chunk so that the field is next.
-        for _ in 0..(*name_size) {
-            let _ = self
-                .cursor
-                .next()
-                .expect("Expected to jump over a name chunk byte...");
+// -- Reader<'a>
+
+/// A cursor over the body of a command, every read fails cleanly on truncated input.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(invalid("truncated command"));
         }
 
-        // Extract out the name str.
-        let name_start = name_idx + 1;
-        let name = {
-            let range = name_start..(name_start + *name_size as usize);
-            let slice = &self.inner.frame.bytes[range];
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
 
-            std::str::from_utf8(slice).unwrap_or("INVALID.UTF-8")
-        };
+    /// A string prefixed by its length in one octet.
+    fn short(&mut self) -> io::Result<&'a [u8]> {
+        let size = self.take(1)?[0];
+        self.take(size as usize)
+    }
 
-        // Read four octets into an array and cast it to a u32
-        // It's written like this so that the iterator is advanced simultaneously.
-        let (field_idx, field_size) = {
-            let mut field_size = [0u8; 4];
-            let mut field_idx = 0;
-
-            for idx in 0..4 {
-                let (pos, byte) = self
-                    .cursor
-                    .next()
-                    .map(|(idx, n)| (idx, *n))
-                    .expect("Unexpected EOF");
-                field_idx = pos;
-                field_size[idx] = byte;
-            }
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
 
-            (field_idx + 1, u32::from_be_bytes(field_size) as usize)
-        };
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
 
-        // And now slice out the field.
-        let field = {
-            let range = field_idx..(field_idx + field_size as usize);
-            let slice = &self.inner.frame.bytes[range];
+    /// The properties of a READY or INITIATE command.
+    fn metadata(&mut self) -> io::Result<Metadata> {
+        let mut properties = vec![];
 
-            std::str::from_utf8(slice).unwrap_or("INVALID.UTF-8")
-        };
+        while !self.bytes.is_empty() {
+            let name = std::str::from_utf8(self.short()?)
+                .map_err(|_| invalid("property name is not valid UTF-8"))?;
+
+            if name.is_empty() {
+                return Err(invalid("empty property name"));
+            }
+
+            let size = self.u32()?;
+            let value = self.take(size as usize)?;
 
-        // Finally jump over the current field chunk.
-        for _ in 0..field_size {
-            let _ = self.cursor.next();
+            properties.push((name.to_string(), value.to_vec()));
         }
 
-        Some((name, field))
+        Ok(Metadata::new(properties))
     }
+
+    fn finish(&self) -> io::Result<()> {
+        match self.bytes.is_empty() {
+            true => Ok(()),
+            false => Err(invalid("trailing bytes after command")),
+        }
+    }
+}
+
+/// Append `bytes` prefixed by its length in one octet.
+fn put_short(bytes: &[u8], buf: &mut Vec<u8>) -> io::Result<()> {
+    let size = u8::try_from(bytes.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "command fields can not be longer than 255 bytes",
+        )
+    })?;
+
+    buf.push(size);
+    buf.extend_from_slice(bytes);
+    Ok(())
 }
 
-// -- Command<'_>
+fn put_metadata(metadata: &Metadata, buf: &mut Vec<u8>) -> io::Result<()> {
+    for (name, value) in metadata.iter() {
+        if name.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "property names can not be empty",
+            ));
+        }
+
+        let size = u32::try_from(value.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "property value is too long")
+        })?;
+
+        put_short(name.as_bytes(), buf)?;
+        buf.extend_from_slice(&size.to_be_bytes());
+        buf.extend_from_slice(value);
+    }
 
-pub struct Command<'a> {
-    pub(crate) frame: Frame<'a>,
+    Ok(())
 }
 
-impl<'a> Command<'a> {
-    pub fn new(frame: Frame<'a>) -> Self {
-        Self { frame }
+fn put_context(context: &[u8], buf: &mut Vec<u8>) -> io::Result<()> {
+    if context.len() > MAX_PING_CONTEXT {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "ping context can not be longer than 16 bytes",
+        ));
     }
 
+    buf.extend_from_slice(context);
+    Ok(())
+}
+
+// -- Command
+
+/// A ZMTP command, the body of a command frame.
+///
+/// HELLO, WELCOME and INITIATE are laid out as the PLAIN mechanism has them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// The properties of the sender, this completes a handshake.
+    Ready(Metadata),
+
+    /// A fatal error, the sender closes the connection after sending it.
+    Error(String),
+
+    /// Subscribe to messages starting with a prefix (ZMTP 3.1).
+    Subscribe(Vec<u8>),
+
+    /// Cancel a subscription (ZMTP 3.1).
+    Cancel(Vec<u8>),
+
+    /// A heartbeat, `ttl` is in tenths of a second.
+    Ping { ttl: u16, context: Vec<u8> },
+
+    /// The answer to a heartbeat, echoing its context.
+    Pong(Vec<u8>),
+
+    /// Join a group.
+    Join(Vec<u8>),
+
+    /// Leave a group.
+    Leave(Vec<u8>),
+
+    /// A PLAIN client's credentials.
+    Hello {
+        username: Vec<u8>,
+        password: Vec<u8>,
+    },
+
+    /// A PLAIN server accepted the credentials.
+    Welcome,
+
+    /// The properties of a PLAIN client.
+    Initiate(Metadata),
+
+    /// Any other command, peers must ignore the ones they do not know.
+    Unknown { name: String, data: Vec<u8> },
+}
+
+impl Command {
+    /// The name this command goes by on the wire.
     pub fn name(&self) -> &str {
-        let idx = if self.frame.bytes[0] == 0x4 { 2 } else { 9 };
-        let size = self.frame.bytes[idx];
-        let start = idx + 1;
-        let end = start + (size as usize);
-        let st = std::str::from_utf8(&self.frame.bytes[start..end]).unwrap_or("INVALID.UTF-8");
-        st
-    }
-
-    /// Get an iterator over the NULL properties of this command.
-    ///
-    /// This frame is only sent once after a handshake only if the security
-    /// mechanism is NULL.
-    #[inline]
-    pub fn null_ready_properties(&self) -> Option<impl Iterator<Item = (&str, &str)>> {
-        if self.name() != "READY" {
-            return None;
+        match self {
+            Self::Ready(_) => "READY",
+            Self::Error(_) => "ERROR",
+            Self::Subscribe(_) => "SUBSCRIBE",
+            Self::Cancel(_) => "CANCEL",
+            Self::Ping { .. } => "PING",
+            Self::Pong(_) => "PONG",
+            Self::Join(_) => "JOIN",
+            Self::Leave(_) => "LEAVE",
+            Self::Hello { .. } => "HELLO",
+            Self::Welcome => "WELCOME",
+            Self::Initiate(_) => "INITIATE",
+            Self::Unknown { name, .. } => name,
         }
+    }
 
-        let cursor = self
-            .frame
-            .bytes
-            .iter()
-            .enumerate()
-            // Skip ahead to the command-metadata/properties index
-            // which is calculated based of frame size.
-            .skip(if self.frame.bytes[0] == 0x4 { 3 } else { 10 } + self.name().len());
-
-        let it = PropertyIterator {
-            inner: self,
-            cursor,
+    /// Parse the body of a command frame.
+    pub fn decode(body: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes: body };
+
+        let name = std::str::from_utf8(reader.short()?)
+            .map_err(|_| invalid("command name is not valid UTF-8"))?;
+
+        let command = match name {
+            "" => return Err(invalid("empty command name")),
+            "READY" => Self::Ready(reader.metadata()?),
+            "ERROR" => {
+                let reason = String::from_utf8_lossy(reader.short()?).into_owned();
+                Self::Error(reason)
+            }
+            "SUBSCRIBE" => Self::Subscribe(reader.rest().to_vec()),
+            "CANCEL" => Self::Cancel(reader.rest().to_vec()),
+            "PING" => Self::Ping {
+                ttl: reader.u16()?,
+                context: reader.rest().to_vec(),
+            },
+            "PONG" => Self::Pong(reader.rest().to_vec()),
+            "JOIN" => Self::Join(reader.rest().to_vec()),
+            "LEAVE" => Self::Leave(reader.rest().to_vec()),
+            "HELLO" => Self::Hello {
+                username: reader.short()?.to_vec(),
+                password: reader.short()?.to_vec(),
+            },
+            "WELCOME" => Self::Welcome,
+            "INITIATE" => Self::Initiate(reader.metadata()?),
+            _ => Self::Unknown {
+                name: name.to_string(),
+                data: reader.rest().to_vec(),
+            },
         };
 
-        Some(it)
+        reader.finish()?;
+
+        Ok(command)
+    }
+
+    /// Append the body of this command to `buf`.
+    pub fn encode_body(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        put_short(self.name().as_bytes(), buf)?;
+
+        match self {
+            Self::Ready(metadata) | Self::Initiate(metadata) => put_metadata(metadata, buf)?,
+            Self::Error(reason) => put_short(reason.as_bytes(), buf)?,
+            Self::Subscribe(bytes)
+            | Self::Cancel(bytes)
+            | Self::Join(bytes)
+            | Self::Leave(bytes)
+            | Self::Unknown { data: bytes, .. } => buf.extend_from_slice(bytes),
+            Self::Ping { ttl, context } => {
+                buf.extend_from_slice(&ttl.to_be_bytes());
+                put_context(context, buf)?;
+            }
+            Self::Pong(context) => put_context(context, buf)?,
+            Self::Hello { username, password } => {
+                put_short(username, buf)?;
+                put_short(password, buf)?;
+            }
+            Self::Welcome => (),
+        }
+
+        Ok(())
+    }
+
+    /// Encode this command as a frame, long commands get a long size.
+    pub fn to_frame(&self) -> io::Result<FrameBuf> {
+        let mut body = vec![];
+        self.encode_body(&mut body)?;
+
+        let mut bytes = Vec::with_capacity(body.len() + 9);
+        encode_header(FrameKind::Command, body.len(), &mut bytes);
+        bytes.extend_from_slice(&body);
+
+        Ok(FrameBuf::from(bytes))
     }
 }
 
-impl<'a> fmt::Debug for Command<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_fmt(format_args!(
-            "Command {{ name: {:#?}, properties: {:#?} }}",
-            self.name(),
-            self.null_ready_properties()
-                .map(|it| it.collect::<Vec<_>>())
-                .unwrap_or_default(),
-        ))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(properties: &[(&str, &[u8])]) -> Metadata {
+        let properties = properties
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_vec()))
+            .collect();
+
+        Metadata::new(properties)
+    }
+
+    /// Encode `command` both as a body and as a frame, and decode it back.
+    fn round_trip(command: Command) {
+        let mut body = vec![];
+        command.encode_body(&mut body).unwrap();
+        assert_eq!(Command::decode(&body).unwrap(), command);
+
+        let frame = command.to_frame().unwrap();
+        let decoded = frame.as_frame().try_into_command().unwrap().unwrap();
+        assert_eq!(decoded, command);
+    }
+
+    fn encoded(command: Command) -> Vec<u8> {
+        let mut body = vec![];
+        command.encode_body(&mut body).unwrap();
+        body
+    }
+
+    #[test]
+    fn every_command_round_trips() {
+        let commands = vec![
+            Command::Ready(metadata(&[("Socket-Type", b"PUB"), ("Identity", b"")])),
+            Command::Ready(metadata(&[])),
+            Command::Error("no thanks".to_string()),
+            Command::Error(String::new()),
+            Command::Subscribe(b"topic".to_vec()),
+            Command::Subscribe(vec![]),
+            Command::Cancel(b"topic".to_vec()),
+            Command::Ping {
+                ttl: 300,
+                context: b"context".to_vec(),
+            },
+            Command::Ping {
+                ttl: 0,
+                context: vec![],
+            },
+            Command::Pong(vec![0xFF; MAX_PING_CONTEXT]),
+            Command::Join(b"group".to_vec()),
+            Command::Leave(b"group".to_vec()),
+            Command::Hello {
+                username: b"admin".to_vec(),
+                password: b"secret".to_vec(),
+            },
+            Command::Welcome,
+            Command::Initiate(metadata(&[("Socket-Type", b"REQ")])),
+            Command::Unknown {
+                name: "FOO".to_string(),
+                data: b"\x00bar".to_vec(),
+            },
+        ];
+
+        for command in commands {
+            round_trip(command);
+        }
+    }
+
+    #[test]
+    fn commands_are_laid_out_as_zmtp_has_them() {
+        assert_eq!(
+            encoded(Command::Ready(metadata(&[("Socket-Type", b"PUB")]))),
+            b"\x05READY\x0BSocket-Type\x00\x00\x00\x03PUB"
+        );
+        assert_eq!(
+            encoded(Command::Error("bad".to_string())),
+            b"\x05ERROR\x03bad"
+        );
+        assert_eq!(
+            encoded(Command::Subscribe(b"a".to_vec())),
+            b"\x09SUBSCRIBEa"
+        );
+        assert_eq!(encoded(Command::Cancel(b"a".to_vec())), b"\x06CANCELa");
+        assert_eq!(
+            encoded(Command::Ping {
+                ttl: 10,
+                context: b"hi".to_vec()
+            }),
+            b"\x04PING\x00\x0Ahi"
+        );
+        assert_eq!(encoded(Command::Pong(b"hi".to_vec())), b"\x04PONGhi");
+        assert_eq!(
+            encoded(Command::Hello {
+                username: b"u".to_vec(),
+                password: b"pw".to_vec()
+            }),
+            b"\x05HELLO\x01u\x02pw"
+        );
+        assert_eq!(encoded(Command::Welcome), b"\x07WELCOME");
+    }
+
+    #[test]
+    fn long_commands_get_a_long_size() {
+        let short = Command::Subscribe(vec![b'a'; 100]).to_frame().unwrap();
+        assert_eq!(short.as_ref()[..2], [0x04, 110]);
+
+        let long = Command::Subscribe(vec![b'a'; 300]).to_frame().unwrap();
+        assert_eq!(long.as_ref()[0], 0x06);
+        assert_eq!(long.as_ref()[1..9], 310u64.to_be_bytes());
+
+        round_trip(Command::Subscribe(vec![b'a'; 300]));
+    }
+
+    #[test]
+    fn malformed_commands_are_invalid() {
+        let malformed: &[&[u8]] = &[
+            // No name at all, or one that's cut short.
+            b"",
+            b"\x05REA",
+            // Names that are empty or not UTF-8.
+            b"\x00",
+            b"\x02\xFF\xFE",
+            // Properties with their size or value cut short.
+            b"\x05READY\x0BSocket-Type\x00\x00",
+            b"\x05READY\x0BSocket-Type\x00\x00\x00\x04PU",
+            b"\x05READY\x0BSocket-Ty",
+            // Property names that are empty or not UTF-8.
+            b"\x05READY\x00\x00\x00\x00\x00",
+            b"\x05READY\x02\xFF\xFE\x00\x00\x00\x00",
+            b"\x08INITIATE\x00\x00\x00\x00\x00",
+            // Fields that are cut short.
+            b"\x05ERROR",
+            b"\x05ERROR\x05oops",
+            b"\x04PING\x00",
+            b"\x05HELLO\x01u",
+            // Bytes past the end of the command.
+            b"\x05ERROR\x02noextra",
+            b"\x05HELLO\x01u\x02pwextra",
+            b"\x07WELCOMEextra",
+        ];
+
+        for body in malformed {
+            let err = Command::decode(body).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", body);
+        }
+    }
+
+    #[test]
+    fn error_reasons_need_not_be_utf8() {
+        assert_eq!(
+            Command::decode(b"\x05ERROR\x02\xFF\xFE").unwrap(),
+            Command::Error("\u{FFFD}\u{FFFD}".to_string())
+        );
+    }
+
+    #[test]
+    fn what_does_not_fit_is_not_encoded() {
+        let unencodable = vec![
+            Command::Ping {
+                ttl: 0,
+                context: vec![0; MAX_PING_CONTEXT + 1],
+            },
+            Command::Pong(vec![0; MAX_PING_CONTEXT + 1]),
+            Command::Error("x".repeat(256)),
+            Command::Hello {
+                username: vec![b'u'; 256],
+                password: vec![],
+            },
+            Command::Ready(metadata(&[("", b"value")])),
+            Command::Ready(metadata(&[(&"x".repeat(256), b"value")])),
+            Command::Unknown {
+                name: "X".repeat(256),
+                data: vec![],
+            },
+        ];
+
+        for command in unencodable {
+            let err = command.to_frame().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", command);
+        }
     }
 }
@@ -1,6 +1,10 @@
-use std::convert::TryInto;
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, IoSlice},
+    ops::Range,
+};
 
-use super::{Command, Message};
+use super::{Command, Message, Part};
 
 // -- FrameBuf
 
@@ -33,53 +37,19 @@ impl FrameBuf {
         Self { bytes }
     }
 
-    pub fn short_command<'a, I>(name: &str, properties: Option<I>) -> Self
-    where
-        I: IntoIterator<Item = (&'a str, &'a str)>,
-    {
-        assert_eq!(name.len(), name.as_bytes().len());
-
-        let mut bytes = vec![
-            // SHORT COMMAND
-            0x4,
-            // LENGTH OF ENTIRE FRAME
-            0x00,
-            // name as cstr length
-            name.len() as u8,
-        ];
-
-        let body: Vec<u8> = match properties {
-            None => vec![],
-            Some(it) => {
-                let mut payload = vec![];
-
-                // For every property:
-                //
-                //  1) push the name length as u8
-                //  2) extend with the name bytes
-                //  3) push the field length as u64 in network byte order
-                //  4) extend with the field bytes
-                for (st, field) in it.into_iter() {
-                    match st.len().try_into() {
-                        Ok(length) => payload.push(length),
-                        Err(_) => panic!("property names can not be longer than 255 bytes."),
-                    }
-
-                    payload.extend_from_slice(st.as_bytes());
-                    payload.extend_from_slice(&u32::to_be_bytes(field.len() as u32) as &[_]);
-                    payload.extend_from_slice(field.as_bytes());
-                }
-
-                payload
-            }
+    /// Encode a single part of a multipart message as a frame.
+    ///
+    /// `more` marks that further parts of the same message follow this one.
+    pub fn message_part(body: &[u8], more: bool) -> Self {
+        let kind = match more {
+            true => FrameKind::MessagePart,
+            false => FrameKind::MessageTail,
         };
 
-        bytes.extend_from_slice(name.as_bytes());
-        bytes.extend_from_slice(body.as_slice());
+        let mut bytes = Vec::with_capacity(body.len() + 9);
 
-        // Size is size_of(frame) - 2
-        // (don't include the flags byte or the size byte itself.)
-        bytes[1] = (bytes.len() - 2).try_into().unwrap();
+        encode_header(kind, body.len(), &mut bytes);
+        bytes.extend_from_slice(body);
 
         Self { bytes }
     }
@@ -89,6 +59,109 @@ impl FrameBuf {
     }
 }
 
+/// Given the flags octet of a frame, return how many octets its size field takes up.
+#[inline]
+pub fn size_octets(flags: u8) -> Option<usize> {
+    match flags {
+        0x0 | 0x1 | 0x4 => Some(1),
+        0x2 | 0x3 | 0x6 => Some(8),
+        _ => None,
+    }
+}
+
+/// Append the flags and size octets of a `kind` frame with a body of `size` bytes to `buf`.
+///
+/// Bodies of up to 255 bytes get a short (one octet) size, anything longer
+/// gets a long (eight octet, network byte order) size.
+pub fn encode_header(kind: FrameKind, size: usize, buf: &mut Vec<u8>) {
+    let flags = match kind {
+        FrameKind::MessageTail => 0x0,
+        FrameKind::MessagePart => 0x1,
+        FrameKind::Command => 0x4,
+    };
+
+    match u8::try_from(size) {
+        Ok(short) => buf.extend_from_slice(&[flags, short]),
+        Err(_) => {
+            buf.push(flags | 0x2);
+            buf.extend_from_slice(&(size as u64).to_be_bytes());
+        }
+    }
+}
+
+// -- Encoder
+
+/// Bodies up to this size are copied in after their header rather than getting an `IoSlice` of their own.
+const COALESCE_BODY: usize = 512;
+
+/// Where the bytes of an `IoSlice` produced by the `Encoder` come from.
+#[derive(Debug)]
+enum Segment {
+    /// A range of the encoder's scratch buffer.
+    Scratch(Range<usize>),
+
+    /// The body of the part at this index.
+    Body(usize),
+}
+
+/// Encodes multi-part messages for vectored writes.
+///
+/// Frame headers, and bodies small enough that copying them beats another
+/// `IoSlice`, are packed into a scratch buffer that is reused between
+/// messages. Larger bodies are written straight from the caller's buffers.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    scratch: Vec<u8>,
+    segments: Vec<Segment>,
+}
+
+impl Encoder {
+    /// Encode `message` as frames, in the order they should be written.
+    pub fn encode<'a>(&'a mut self, message: Message<'a>) -> io::Result<Vec<IoSlice<'a>>> {
+        let last = message
+            .len()
+            .checked_sub(1)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        self.scratch.clear();
+        self.segments.clear();
+
+        let mut start = 0;
+
+        for (index, body) in message.iter().enumerate() {
+            let kind = match index == last {
+                true => FrameKind::MessageTail,
+                false => FrameKind::MessagePart,
+            };
+
+            encode_header(kind, body.len(), &mut self.scratch);
+
+            if body.len() <= COALESCE_BODY {
+                self.scratch.extend_from_slice(body);
+            } else {
+                self.segments
+                    .push(Segment::Scratch(start..self.scratch.len()));
+                self.segments.push(Segment::Body(index));
+                start = self.scratch.len();
+            }
+        }
+
+        if start < self.scratch.len() {
+            self.segments
+                .push(Segment::Scratch(start..self.scratch.len()));
+        }
+
+        let scratch = &self.scratch;
+
+        let slices = self.segments.iter().map(|segment| match segment {
+            Segment::Scratch(range) => IoSlice::new(&scratch[range.clone()]),
+            Segment::Body(index) => IoSlice::new(message.get(*index).unwrap_or_default()),
+        });
+
+        Ok(slices.collect())
+    }
+}
+
 // -- Frame<'a>
 
 /// A slice of frame (akin to `str` or `Path`)
@@ -102,20 +175,63 @@ impl<'a> Frame<'a> {
         Self { bytes }
     }
 
-    pub fn try_into_command(self) -> Option<Command<'a>> {
+    /// Try to split one complete frame off the front of `bytes`.
+    ///
+    /// `Ok(None)` is returned if `bytes` does not (yet) hold an entire frame.
+    pub fn parse(bytes: &'a [u8]) -> io::Result<Option<Self>> {
+        let flags = match bytes.first() {
+            Some(flags) => *flags,
+            None => return Ok(None),
+        };
+
+        let octets =
+            size_octets(flags).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+
+        let header = match bytes.get(1..=octets) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let size = match header.try_into() {
+            Ok(long) => usize::try_from(u64::from_be_bytes(long)).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "peer sent a frame too large to address",
+                )
+            })?,
+            Err(_) => header[0] as usize,
+        };
+
+        match (1 + octets).checked_add(size) {
+            Some(length) if bytes.len() >= length => Ok(Some(Self {
+                bytes: &bytes[..length],
+            })),
+            Some(_) => Ok(None),
+            None => Err(io::Error::from(io::ErrorKind::InvalidData)),
+        }
+    }
+
+    /// Parse a command frame, `None` if this frame is not a command.
+    pub fn try_into_command(self) -> Option<io::Result<Command>> {
         match self.kind()? {
-            FrameKind::Command => Some(Command { frame: self }),
+            FrameKind::Command => Some(Command::decode(self.body()?)),
             _ => None,
         }
     }
 
-    pub fn try_into_message(self) -> Option<Message<'a>> {
+    /// The body of the frame, everything after the flags and size.
+    pub fn body(&self) -> Option<&'a [u8]> {
+        let octets = size_octets(*self.bytes.first()?)?;
+        self.bytes.get(1 + octets..)
+    }
+
+    pub fn try_into_part(self) -> Option<Part<'a>> {
         match self.kind()? {
-            FrameKind::MessagePart => Some(Message {
+            FrameKind::MessagePart => Some(Part {
                 frame: self,
                 is_last: false,
             }),
-            FrameKind::MessageTail => Some(Message {
+            FrameKind::MessageTail => Some(Part {
                 frame: self,
                 is_last: true,
             }),
@@ -125,13 +241,12 @@ impl<'a> Frame<'a> {
 
     /// Get the size of the frame.
     pub fn size(&self) -> Option<usize> {
-        match self.bytes.get(0)? {
+        match self.bytes.first()? {
             0x0 | 0x1 | 0x4 => Some(*self.bytes.get(1)? as usize),
 
             0x2 | 0x3 | 0x6 => {
-                let slice = self.bytes.get(1..)?.try_into().ok()?;
-                let size = u64::from_be_bytes(slice);
-                Some(size as usize)
+                let slice = self.bytes.get(1..9)?.try_into().ok()?;
+                usize::try_from(u64::from_be_bytes(slice)).ok()
             }
 
             _ => None,
@@ -140,7 +255,7 @@ impl<'a> Frame<'a> {
 
     /// The type of frame.
     pub fn kind(&self) -> Option<FrameKind> {
-        let kind = match self.bytes.get(0)? {
+        let kind = match self.bytes.first()? {
             // short/long message tail.
             0x0 | 0x2 => FrameKind::MessageTail,
 
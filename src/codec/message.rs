@@ -1,14 +1,20 @@
+use std::{fmt, iter::FromIterator, ops::Index, sync::Arc};
+
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+
 use super::Frame;
 
-// -- Message
+// -- Part<'a>
 
+/// A message frame, one part of a (possibly) multipart message.
 #[derive(Debug)]
-pub struct Message<'a> {
+pub struct Part<'a> {
     pub(crate) frame: Frame<'a>,
     pub(crate) is_last: bool,
 }
 
-impl<'a> Message<'a> {
+impl<'a> Part<'a> {
     #[inline]
     pub fn is_last(&self) -> bool {
         self.is_last
@@ -24,3 +30,551 @@ impl<'a> Message<'a> {
         &self.frame.bytes[start..]
     }
 }
+
+// -- Metadata
+
+/// What the peer told us about itself during the handshake.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    properties: Vec<(String, Vec<u8>)>,
+}
+
+impl Metadata {
+    pub(crate) fn new(properties: Vec<(String, Vec<u8>)>) -> Self {
+        Self { properties }
+    }
+
+    /// Look up a handshake property, property names are case-insensitive.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.properties
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// The peer's socket type, e.g. `"PUSH"`.
+    pub fn socket_type(&self) -> Option<&str> {
+        self.get("Socket-Type")
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// The routing id the peer announced, if any.
+    pub fn routing_id(&self) -> Option<&[u8]> {
+        self.get("Identity").filter(|id| !id.is_empty())
+    }
+
+    /// Iterate over every handshake property.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.properties
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+}
+
+// -- Message<'a>
+
+/// A borrowed multipart message (akin to `str` or `Path`.)
+#[derive(Clone, Copy)]
+pub struct Message<'a> {
+    bytes: &'a [u8],
+    ends: &'a [usize],
+
+    /// Shared parts, if there are any then these are all of the parts.
+    #[cfg(feature = "bytes")]
+    shared: &'a [Bytes],
+}
+
+impl<'a> Message<'a> {
+    /// The amount of parts.
+    #[inline]
+    pub fn len(&self) -> usize {
+        #[cfg(feature = "bytes")]
+        if !self.shared.is_empty() {
+            return self.shared.len();
+        }
+
+        self.ends.len()
+    }
+
+    /// Whether there are no parts at all, such a message can't be sent.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the part at `index`.
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        #[cfg(feature = "bytes")]
+        if !self.shared.is_empty() {
+            return self.shared.get(index).map(|part| &part[..]);
+        }
+
+        let end = *self.ends.get(index)?;
+        let start = match index {
+            0 => 0,
+            _ => self.ends[index - 1],
+        };
+
+        Some(&self.bytes[start..end])
+    }
+
+    /// The first part, e.g. a topic.
+    #[inline]
+    pub fn first(&self) -> Option<&'a [u8]> {
+        self.get(0)
+    }
+
+    /// The last part.
+    #[inline]
+    pub fn last(&self) -> Option<&'a [u8]> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    /// Iterate over the parts.
+    #[inline]
+    pub fn iter(&self) -> Parts<'a> {
+        Parts {
+            message: *self,
+            front: 0,
+            back: self.len(),
+        }
+    }
+
+    /// The total size of all the parts.
+    #[inline]
+    pub fn size(&self) -> usize {
+        #[cfg(feature = "bytes")]
+        if !self.shared.is_empty() {
+            return self.shared.iter().map(Bytes::len).sum();
+        }
+
+        self.bytes.len()
+    }
+
+    /// Copy the parts into an owned `MessageBuf`.
+    ///
+    /// Shared parts aren't copied, they're reference counted.
+    pub fn to_message_buf(&self) -> MessageBuf {
+        MessageBuf {
+            bytes: self.bytes.to_vec(),
+            ends: self.ends.to_vec(),
+            #[cfg(feature = "bytes")]
+            shared: self.shared.to_vec(),
+            metadata: None,
+        }
+    }
+}
+
+impl<'a> IntoIterator for Message<'a> {
+    type Item = &'a [u8];
+    type IntoIter = Parts<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Debug for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, 'b> PartialEq<Message<'b>> for Message<'a> {
+    fn eq(&self, other: &Message<'b>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+// -- Parts<'a>
+
+/// An iterator over the parts of a message.
+#[derive(Debug, Clone)]
+pub struct Parts<'a> {
+    message: Message<'a>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for Parts<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+
+        self.front += 1;
+        self.message.get(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.back - self.front;
+        (left, Some(left))
+    }
+}
+
+impl DoubleEndedIterator for Parts<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        self.message.get(self.back)
+    }
+}
+
+impl ExactSizeIterator for Parts<'_> {}
+
+// -- MessageBuf
+
+/// An owned multipart message.
+///
+/// All the parts live in one buffer, next to where each of them ends, so a
+/// message costs two allocations no matter how many parts it has. Received
+/// messages also carry the [`Metadata`] of the peer they came from.
+///
+/// With the `bytes` feature parts may also be shared [`Bytes`](bytes::Bytes)
+/// instead. Such parts are sent without being copied and received
+/// messages are made of slices of the buffer they were read into.
+///
+/// ```rust
+/// use zedmq::MessageBuf;
+///
+/// let mut message = MessageBuf::from(("topic", "hello"));
+/// message.push(b"world");
+///
+/// assert_eq!(message.len(), 3);
+/// assert_eq!(&message[1], b"hello");
+/// assert_eq!(message.pop().as_deref(), Some(&b"world"[..]));
+/// assert_eq!(Vec::<Vec<u8>>::from(message), vec![b"topic".to_vec(), b"hello".to_vec()]);
+/// ```
+#[derive(Clone, Default)]
+pub struct MessageBuf {
+    bytes: Vec<u8>,
+    ends: Vec<usize>,
+
+    /// Shared parts, if there are any then these are all of the parts.
+    #[cfg(feature = "bytes")]
+    shared: Vec<Bytes>,
+    metadata: Option<Arc<Metadata>>,
+}
+
+impl MessageBuf {
+    /// A message without any parts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A message with room for `parts` parts of `size` bytes in total.
+    pub fn with_capacity(parts: usize, size: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(size),
+            ends: Vec::with_capacity(parts),
+            #[cfg(feature = "bytes")]
+            shared: Vec::new(),
+            metadata: None,
+        }
+    }
+
+    /// Borrow the message.
+    #[inline]
+    pub fn as_message(&self) -> Message<'_> {
+        Message {
+            bytes: &self.bytes,
+            ends: &self.ends,
+            #[cfg(feature = "bytes")]
+            shared: &self.shared,
+        }
+    }
+
+    /// Append a part.
+    pub fn push<B>(&mut self, part: B)
+    where
+        B: AsRef<[u8]>,
+    {
+        #[cfg(feature = "bytes")]
+        if !self.shared.is_empty() {
+            return self.shared.push(Bytes::copy_from_slice(part.as_ref()));
+        }
+
+        self.bytes.extend_from_slice(part.as_ref());
+        self.ends.push(self.bytes.len());
+    }
+
+    /// Append a shared part without copying it.
+    #[cfg(feature = "bytes")]
+    pub fn push_bytes(&mut self, part: Bytes) {
+        // The parts we already own become slices of one shared buffer.
+        if !self.ends.is_empty() {
+            let bytes = Bytes::from(std::mem::take(&mut self.bytes));
+            let mut start = 0;
+
+            for end in self.ends.drain(..) {
+                self.shared.push(bytes.slice(start..end));
+                start = end;
+            }
+        }
+
+        self.shared.push(part);
+    }
+
+    /// Get the part at `index` as `Bytes`, only owned parts are copied.
+    #[cfg(feature = "bytes")]
+    pub fn get_bytes(&self, index: usize) -> Option<Bytes> {
+        match self.shared.is_empty() {
+            true => self.get(index).map(Bytes::copy_from_slice),
+            false => self.shared.get(index).cloned(),
+        }
+    }
+
+    /// Remove the last part.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        #[cfg(feature = "bytes")]
+        if !self.shared.is_empty() {
+            return self.shared.pop().map(|part| part.to_vec());
+        }
+
+        self.ends.pop()?;
+
+        let start = self.ends.last().copied().unwrap_or(0);
+
+        Some(self.bytes.split_off(start))
+    }
+
+    /// Remove every part, keeping the allocations around for reuse.
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.ends.clear();
+        #[cfg(feature = "bytes")]
+        self.shared.clear();
+        self.metadata = None;
+    }
+
+    /// The amount of parts.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.as_message().len()
+    }
+
+    /// Whether there are no parts at all, such a message can't be sent.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the part at `index`.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.as_message().get(index)
+    }
+
+    /// The first part, e.g. a topic.
+    #[inline]
+    pub fn first(&self) -> Option<&[u8]> {
+        self.as_message().first()
+    }
+
+    /// The last part.
+    #[inline]
+    pub fn last(&self) -> Option<&[u8]> {
+        self.as_message().last()
+    }
+
+    /// Iterate over the parts.
+    #[inline]
+    pub fn iter(&self) -> Parts<'_> {
+        self.as_message().iter()
+    }
+
+    /// The total size of all the parts.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.as_message().size()
+    }
+
+    /// What the peer this message was received from told us about itself.
+    #[inline]
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_deref()
+    }
+
+    pub(crate) fn set_metadata(&mut self, metadata: Option<Arc<Metadata>>) {
+        self.metadata = metadata;
+    }
+}
+
+impl fmt::Debug for MessageBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_message(), f)
+    }
+}
+
+/// Messages are equal if their parts are, metadata is not compared.
+impl PartialEq for MessageBuf {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_message() == other.as_message()
+    }
+}
+
+impl Eq for MessageBuf {}
+
+impl PartialEq<Vec<Vec<u8>>> for MessageBuf {
+    fn eq(&self, other: &Vec<Vec<u8>>) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a == b.as_slice())
+    }
+}
+
+impl PartialEq<MessageBuf> for Vec<Vec<u8>> {
+    fn eq(&self, other: &MessageBuf) -> bool {
+        other == self
+    }
+}
+
+impl Index<usize> for MessageBuf {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.get(index) {
+            Some(part) => part,
+            None => panic!(
+                "part index {} out of range for a message of {} parts",
+                index,
+                self.len()
+            ),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a MessageBuf {
+    type Item = &'a [u8];
+    type IntoIter = Parts<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<B> FromIterator<B> for MessageBuf
+where
+    B: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = B>>(iter: I) -> Self {
+        let mut message = Self::new();
+        message.extend(iter);
+        message
+    }
+}
+
+impl<B> Extend<B> for MessageBuf
+where
+    B: AsRef<[u8]>,
+{
+    fn extend<I: IntoIterator<Item = B>>(&mut self, iter: I) {
+        for part in iter {
+            self.push(part);
+        }
+    }
+}
+
+impl From<Message<'_>> for MessageBuf {
+    fn from(message: Message<'_>) -> Self {
+        message.to_message_buf()
+    }
+}
+
+impl From<&MessageBuf> for MessageBuf {
+    fn from(message: &MessageBuf) -> Self {
+        message.clone()
+    }
+}
+
+impl From<Vec<Vec<u8>>> for MessageBuf {
+    fn from(parts: Vec<Vec<u8>>) -> Self {
+        parts.iter().collect()
+    }
+}
+
+impl From<Vec<&[u8]>> for MessageBuf {
+    fn from(parts: Vec<&[u8]>) -> Self {
+        parts.iter().collect()
+    }
+}
+
+impl From<MessageBuf> for Vec<Vec<u8>> {
+    fn from(message: MessageBuf) -> Self {
+        message.iter().map(<[u8]>::to_vec).collect()
+    }
+}
+
+impl<B, const N: usize> From<[B; N]> for MessageBuf
+where
+    B: AsRef<[u8]>,
+{
+    fn from(parts: [B; N]) -> Self {
+        parts.iter().collect()
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<Bytes> for MessageBuf {
+    fn from(part: Bytes) -> Self {
+        let mut message = Self::new();
+        message.push_bytes(part);
+        message
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<Vec<Bytes>> for MessageBuf {
+    fn from(shared: Vec<Bytes>) -> Self {
+        Self {
+            shared,
+            ..Self::new()
+        }
+    }
+}
+
+/// Single part messages.
+macro_rules! impl_from_part {
+    ($( $ty:ty ),+) => {
+        $(
+            impl From<$ty> for MessageBuf {
+                fn from(part: $ty) -> Self {
+                    std::iter::once(part).collect()
+                }
+            }
+        )+
+    };
+}
+
+impl_from_part!(Vec<u8>, &[u8], &Vec<u8>, String, &str, &String);
+
+/// Multipart messages out of tuples of parts.
+macro_rules! impl_from_tuple {
+    ($( ($( $name:ident ),+) ),+) => {
+        $(
+            impl<$( $name ),+> From<($( $name, )+)> for MessageBuf
+            where
+                $( $name: AsRef<[u8]>, )+
+            {
+                #[allow(non_snake_case)]
+                fn from(($( $name, )+): ($( $name, )+)) -> Self {
+                    let mut message = Self::new();
+                    $( message.push($name); )+
+                    message
+                }
+            }
+        )+
+    };
+}
+
+impl_from_tuple!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F)
+);
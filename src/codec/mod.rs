@@ -5,6 +5,8 @@ pub mod protocol;
 
 use std::convert::TryInto;
 
+use crate::Mechanism;
+
 pub use self::{command::*, frame::*, message::*};
 pub(crate) use protocol::*;
 
@@ -13,6 +15,7 @@ pub(crate) use protocol::*;
 #[derive(Debug, Default)]
 pub struct Greeting {
     as_server: bool,
+    mechanism: Mechanism,
 }
 
 impl Greeting {
@@ -26,11 +29,13 @@ impl Greeting {
         self
     }
 
-    // pub fn security(&mut self, _security: ()) -> &mut Self {
-    //     unimplemented!();
-    // }
+    /// Set the security mechanism of the greeting.
+    pub fn mechanism(&mut self, mechanism: Mechanism) -> &mut Self {
+        self.mechanism = mechanism;
+        self
+    }
 
-    pub fn into_parts(&self) -> ([u8; 12], [u8; 52]) {
+    pub fn to_parts(&self) -> ([u8; 12], [u8; 52]) {
         let raw = self.as_bytes();
         let partial = raw[..=11].try_into().unwrap();
         let remaining = raw[12..].try_into().unwrap();
@@ -39,7 +44,6 @@ impl Greeting {
 
     /// Serialize the `Greeting` struct into a raw `[u8; 64]` greeting.
     pub fn as_bytes(&self) -> [u8; 64] {
-        // TODO: Missing a way to specify the security mechanism (currently NULL) and the as-server field (currently false)
         let mut raw = [0u8; 64];
 
         // signature
@@ -49,13 +53,11 @@ impl Greeting {
 
         // version
         raw[10] = 3;
-        raw[11] = 0;
+        raw[11] = 1;
 
-        // Security (NULL)
-        raw[12] = 0x4E;
-        raw[13] = 0x55;
-        raw[14] = 0x4C;
-        raw[15] = 0x4C;
+        // Security, padded with nulls.
+        let mechanism = self.mechanism.name();
+        raw[12..12 + mechanism.len()].copy_from_slice(mechanism);
 
         // as-server
         raw[32] = self.as_server as u8;
@@ -1,97 +1,1155 @@
-use std::io::{self, Read, Write};
+use std::collections::VecDeque;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, IoSlice};
+use std::sync::Arc;
 
-use crate::stream::Transport;
+#[cfg(feature = "bytes")]
+use bytes::{Buf, BytesMut};
 
-use super::FrameBuf;
+use super::{
+    Command, Frame, FrameBuf, FrameKind, Greeting, Message, MessageBuf, Metadata, MAX_PING_CONTEXT,
+};
+use crate::{Mechanism, PeerError, SocketOptions};
 
-/// A builder struct used to handle `greeting` and `handshake` steps.
-pub(crate) struct ZMTP {
-    // security: Option<()>,
-    transport: Transport,
+/// The size of the signature and version chunk at the start of a greeting.
+const PARTIAL_GREETING: usize = 12;
+
+/// The size of the rest of the greeting (mechanism, as-server and filler.)
+const REMAINING_GREETING: usize = 52;
+
+/// The ZMTP version we speak.
+const VERSION: (u8, u8) = (3, 1);
+
+/// How much is read from the peer at a time, unless a frame needs more than that.
+pub const READ_CHUNK: usize = 64 * 1024;
+
+/// Whether any of the complete messages among `frames` is one that `accept` takes.
+fn any_accepted<'a, I>(frames: I, accept: &mut dyn FnMut(&[u8]) -> bool) -> bool
+where
+    I: Iterator<Item = Frame<'a>>,
+{
+    let mut head = None;
+
+    for part in frames.filter_map(Frame::try_into_part) {
+        let first = *head.get_or_insert(part.body());
+
+        if part.is_last() {
+            if accept(first) {
+                return true;
+            }
+
+            head = None;
+        }
+    }
+
+    false
 }
 
-impl ZMTP {
-    /// The "connect" side of the connection.
-    pub fn connect<F>(produce_transport: F) -> io::Result<Self>
-    where
-        F: FnOnce() -> io::Result<Transport>,
-    {
-        let transport = produce_transport()?;
-
-        Ok(Self {
-            // security: None,
-            transport,
-        })
-    }
-
-    /// Perform the greeting step of the ZMTP spec.
-    pub fn greet(mut self, (_major, _minor, _): (u8, u8, u8), as_server: bool) -> io::Result<Self> {
-        let (partial, remaining) = {
-            let mut greeting = crate::codec::Greeting::build();
-            greeting.as_server(as_server);
-            // greeting.security(self.security)
-            greeting.into_parts()
+/// The frames in `bytes`, up to the first incomplete (or broken) one.
+fn frames(mut bytes: &[u8]) -> impl Iterator<Item = Frame<'_>> {
+    std::iter::from_fn(move || {
+        let frame = Frame::parse(bytes).ok()??;
+        bytes = &bytes[frame.bytes.len()..];
+        Some(frame)
+    })
+}
+
+/// The states a ZMTP connection moves through, in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    /// Waiting on the signature and version of the peer's greeting.
+    Signature,
+
+    /// Waiting on the mechanism and the rest of the peer's greeting.
+    Greeting,
+
+    /// Waiting on the peer's READY command.
+    Handshake,
+
+    /// The handshake is complete and traffic may flow.
+    Ready,
+}
+
+/// The input buffer, with the `bytes` feature received messages are slices of it.
+#[cfg(feature = "bytes")]
+type InputBuf = BytesMut;
+
+#[cfg(not(feature = "bytes"))]
+type InputBuf = Vec<u8>;
+
+/// Something the engine decoded out of the bytes it was fed.
+#[derive(Debug)]
+pub enum Event {
+    /// The peer sent its READY command, the handshake is now complete.
+    Ready,
+
+    /// A command that's up to the socket type to handle, i.e. SUBSCRIBE, CANCEL, JOIN or LEAVE.
+    ///
+    /// Every other command is taken care of by the engine itself.
+    Command(Command),
+
+    /// The flags and body size of a frame taken by [`Engine::poll_header`].
+    Header(FrameKind, u64),
+
+    /// A complete message, decoded into the buffer handed to [`Engine::poll_into`].
+    Message,
+}
+
+/// A sans-IO ZMTP protocol state machine.
+///
+/// The engine never touches a socket. Bytes read from the peer are handed to
+/// [`Engine::feed`], decoded events are pulled out with [`Engine::poll`] and
+/// bytes the peer needs to see are taken from [`Engine::output`]. This lets
+/// the blocking, async and any custom transports drive the same protocol
+/// logic however they like.
+///
+/// The engine also holds the per-peer message queues. Outbound messages
+/// queue up in `output` until written, bounded by the SNDHWM. Inbound
+/// messages aren't queued, they're decoded straight out of the input
+/// buffer as they're polled.
+#[derive(Debug)]
+pub struct Engine {
+    socket_type: &'static str,
+    routing_id: Option<Vec<u8>>,
+    mechanism: Mechanism,
+    peer: Option<Arc<Metadata>>,
+
+    /// The ZMTP version spoken with the peer, the lower of ours and theirs.
+    version: (u8, u8),
+    state: State,
+    input: InputBuf,
+    cursor: usize,
+
+    /// How much of `input` holds bytes from the peer, the rest is spare.
+    filled: usize,
+
+    /// How much of a frame body that's no longer wanted is yet to be thrown away.
+    skip: u64,
+
+    /// The largest message the peer may send, commands included.
+    max_msg_size: Option<u64>,
+
+    /// How much of the current message has been handed out by `poll_header`.
+    streamed: u64,
+    output: Vec<u8>,
+
+    /// How many bytes of output have ever been consumed.
+    consumed: usize,
+
+    /// Where in the output (counting from the very start) each queued message ends.
+    queued: VecDeque<usize>,
+    sndhwm: usize,
+
+    /// Whether only the latest message is taken, older ones are thrown away.
+    conflate: bool,
+}
+
+impl Engine {
+    /// Start a connection as the client side for a socket of type `socket_type`.
+    ///
+    /// The first half of our greeting is queued up immediately.
+    pub fn new(socket_type: &'static str, options: &SocketOptions) -> Self {
+        let (partial, _) = Greeting::build().as_server(false).to_parts();
+
+        Self {
+            socket_type,
+            routing_id: options.routing_id().map(<[u8]>::to_vec),
+            mechanism: options.mechanism(),
+            peer: None,
+            version: VERSION,
+            state: State::Signature,
+            input: InputBuf::new(),
+            cursor: 0,
+            filled: 0,
+            skip: 0,
+            max_msg_size: options.max_msg_size(),
+            streamed: 0,
+            output: partial.to_vec(),
+            consumed: 0,
+            queued: VecDeque::new(),
+            sndhwm: options.sndhwm(),
+            conflate: options.conflate() && matches!(socket_type, "SUB" | "PULL"),
+        }
+    }
+
+    /// Whether messages for the peer are dropped, rather than blocking, at the high-water mark.
+    #[inline]
+    pub fn drops_at_hwm(&self) -> bool {
+        matches!(self.socket_type, "PUB" | "RADIO")
+    }
+
+    /// Whether only the latest message is taken, see [`SocketOption::Conflate`](crate::SocketOption::Conflate).
+    #[inline]
+    pub fn conflates(&self) -> bool {
+        self.conflate
+    }
+
+    /// What the peer told us about itself in its READY command.
+    #[inline]
+    pub fn peer(&self) -> Option<&Arc<Metadata>> {
+        self.peer.as_ref()
+    }
+
+    /// Whether the handshake has been completed.
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        self.state == State::Ready
+    }
+
+    /// Hand over bytes that were received from the peer.
+    #[cfg(test)]
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.read_buf(bytes.len()).copy_from_slice(bytes);
+        self.commit(bytes.len());
+    }
+
+    /// Get `n` bytes of spare input buffer to read from the peer into.
+    ///
+    /// This avoids copying through an intermediate buffer. Whatever was
+    /// actually read is handed over with [`Engine::commit`], until then the
+    /// spare buffer is ignored so giving up on a read half-way is fine.
+    pub fn read_buf(&mut self, n: usize) -> &mut [u8] {
+        self.compact();
+
+        if self.input.len() < self.filled + n {
+            self.input.resize(self.filled + n, 0);
+        }
+
+        &mut self.input[self.filled..self.filled + n]
+    }
+
+    /// Mark the first `n` bytes of the last [`Engine::read_buf`] as received from the peer.
+    #[inline]
+    pub fn commit(&mut self, n: usize) {
+        self.filled += n;
+        self.discard();
+    }
+
+    /// Reclaim the space taken up by already decoded input before growing.
+    fn compact(&mut self) {
+        if self.cursor == self.filled {
+            self.cursor = 0;
+            self.filled = 0;
+        } else if self.cursor > self.input.len() / 2 {
+            self.input.copy_within(self.cursor..self.filled, 0);
+            self.filled -= self.cursor;
+            self.cursor = 0;
+        }
+    }
+
+    /// The bytes that are buffered but not yet decoded.
+    #[inline]
+    fn pending(&self) -> &[u8] {
+        &self.input[self.cursor..self.filled]
+    }
+
+    /// Whether everything the peer sent has been taken, so it may hang up without cutting anything short.
+    #[cfg(feature = "futures")]
+    #[inline]
+    pub fn is_idle(&self) -> bool {
+        self.pending().is_empty() && self.skip == 0
+    }
+
+    /// The minimum amount of bytes that must be fed before `poll` can make progress.
+    ///
+    /// Bodies that are being skipped over are only asked for a chunk at a time.
+    pub fn wants(&self) -> usize {
+        if self.skip > 0 {
+            return self.skip.min(READ_CHUNK as u64) as usize;
+        }
+
+        let pending = self.pending();
+
+        let needed = match self.state {
+            State::Signature => PARTIAL_GREETING,
+            State::Greeting => REMAINING_GREETING,
+            // Complete message parts are skipped over, `poll_into` hands out whole messages.
+            State::Handshake | State::Ready => {
+                let mut offset = 0;
+
+                loop {
+                    let rest = &pending[offset..];
+
+                    let flags = match rest.first() {
+                        None => break offset + 1,
+                        Some(flags) => *flags,
+                    };
+
+                    let length = match super::size_octets(flags) {
+                        None => return 0,
+                        Some(octets) if rest.len() < 1 + octets => break offset + 1 + octets,
+                        Some(1) => 2 + rest[1] as usize,
+                        Some(_) => {
+                            let mut size = [0u8; 8];
+                            size.copy_from_slice(&rest[1..9]);
+                            9usize.saturating_add(
+                                usize::try_from(u64::from_be_bytes(size)).unwrap_or(usize::MAX),
+                            )
+                        }
+                    };
+
+                    if rest.len() < length {
+                        break offset.saturating_add(length);
+                    }
+
+                    // A command in between the parts of a message doesn't end it.
+                    if flags & 0x1 == 0 && (offset == 0 || flags & 0x4 == 0) {
+                        return 0;
+                    }
+
+                    offset += length;
+                }
+            }
+        };
+
+        needed.saturating_sub(pending.len())
+    }
+
+    /// How much spare input buffer to read the peer into next, see [`Engine::read_buf`].
+    ///
+    /// This is at least `READ_CHUNK`, and past that never more than what's
+    /// already buffered. Room for a large frame is made as its bytes come
+    /// in, rather than out of whatever size its header declares.
+    pub fn read_size(&self) -> usize {
+        self.wants().min(self.pending().len()).max(READ_CHUNK)
+    }
+
+    /// Bytes that should be written to the peer.
+    #[inline]
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Mark `n` bytes of `output` as having been written to the peer.
+    #[inline]
+    pub fn consume_output(&mut self, n: usize) {
+        self.output.drain(..n);
+        self.consumed += n;
+
+        while self.queued.front().is_some_and(|end| *end <= self.consumed) {
+            self.queued.pop_front();
+        }
+    }
+
+    /// Whether another message may be queued without exceeding the SNDHWM.
+    #[inline]
+    pub fn is_writable(&self) -> bool {
+        self.sndhwm == 0 || self.queued.len() < self.sndhwm
+    }
+
+    /// Queue up a subscription to, or with `subscribe` unset a cancellation of, `topic`.
+    ///
+    /// ZMTP 3.1 peers are sent a SUBSCRIBE or CANCEL command, 3.0 peers get
+    /// a message holding the topic behind a `1` or `0` byte.
+    pub fn write_subscription(&mut self, topic: &[u8], subscribe: bool) -> io::Result<()> {
+        let frame = match (self.version >= (3, 1), subscribe) {
+            (true, true) => Command::Subscribe(topic.to_vec()).to_frame()?,
+            (true, false) => Command::Cancel(topic.to_vec()).to_frame()?,
+            (false, _) => {
+                let mut body = Vec::with_capacity(1 + topic.len());
+
+                body.push(subscribe as u8);
+                body.extend_from_slice(topic);
+
+                FrameBuf::message_part(&body, false)
+            }
+        };
+
+        self.output.extend_from_slice(frame.as_ref());
+
+        Ok(())
+    }
+
+    /// Encode a multi-part message for the peer.
+    ///
+    /// Once the SNDHWM is reached the message is silently dropped for PUB
+    /// and RADIO sockets, every other socket type gets a `WouldBlock` error
+    /// and should write out some of the output before trying again.
+    pub fn write_message(&mut self, message: Message<'_>) -> io::Result<()> {
+        if message.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        if !self.is_writable() {
+            return match self.drops_at_hwm() {
+                true => Ok(()),
+                false => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+            };
+        }
+
+        let last = message.len() - 1;
+
+        for (index, part) in message.iter().enumerate() {
+            let kind = match index == last {
+                true => FrameKind::MessageTail,
+                false => FrameKind::MessagePart,
+            };
+
+            super::encode_header(kind, part.len(), &mut self.output);
+            self.output.extend_from_slice(part);
+        }
+
+        self.queued.push_back(self.consumed + self.output.len());
+
+        Ok(())
+    }
+
+    /// Queue up what's left of an encoded message after writing some of it straight to the peer.
+    ///
+    /// This only makes sense while `output` is empty, anything else would
+    /// have had to be written before the message.
+    pub fn write_remainder(&mut self, remainder: &[IoSlice<'_>]) {
+        debug_assert!(self.output.is_empty());
+
+        if remainder.iter().all(|slice| slice.is_empty()) {
+            return;
+        }
+
+        for slice in remainder {
+            self.output.extend_from_slice(slice);
+        }
+
+        self.queued.push_back(self.consumed + self.output.len());
+    }
+
+    /// Take `n` bytes of decoded input.
+    fn take(&mut self, n: usize) -> &[u8] {
+        let start = self.cursor;
+        self.cursor += n;
+        &self.input[start..self.cursor]
+    }
+
+    /// Advance the state machine as far as the buffered input allows.
+    ///
+    /// `Ok(None)` means more input is needed. Once the handshake is done
+    /// this does nothing, messages are taken with [`Engine::poll_into`].
+    pub fn poll(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            match self.state {
+                State::Signature => {
+                    if self.pending().len() < PARTIAL_GREETING {
+                        return Ok(None);
+                    }
+
+                    let signature = self.take(PARTIAL_GREETING);
+
+                    if signature[0] != 0xFF || signature[9] & 0x01 != 0x01 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "peer sent an invalid ZMTP signature",
+                        ));
+                    }
+
+                    let version = (signature[10], signature[11]);
+
+                    if version.0 < VERSION.0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("peer speaks ZMTP {}.{}", version.0, version.1),
+                        ));
+                    }
+
+                    self.version = version.min(VERSION);
+
+                    // Send remaining greeting
+                    let (_, remaining) = Greeting::build()
+                        .as_server(false)
+                        .mechanism(self.mechanism)
+                        .to_parts();
+                    self.output.extend_from_slice(&remaining);
+
+                    self.state = State::Greeting;
+                }
+
+                State::Greeting => {
+                    if self.pending().len() < REMAINING_GREETING {
+                        return Ok(None);
+                    }
+
+                    let mechanism = self.mechanism.name();
+                    let remaining = self.take(REMAINING_GREETING);
+
+                    if !remaining[..20].starts_with(mechanism) || remaining[mechanism.len()] != 0 {
+                        return Err(self.reject("unsupported security mechanism"));
+                    }
+
+                    // The NULL mechanism lets both sides send READY straight away.
+                    let handshake = {
+                        let mut properties = vec![(
+                            "Socket-Type".to_string(),
+                            self.socket_type.as_bytes().to_vec(),
+                        )];
+
+                        if let Some(routing_id) = self.routing_id.as_deref() {
+                            properties.push(("Identity".to_string(), routing_id.to_vec()));
+                        }
+
+                        Command::Ready(Metadata::new(properties)).to_frame()?
+                    };
+
+                    self.output.extend_from_slice(handshake.as_ref());
+
+                    self.state = State::Handshake;
+                }
+
+                State::Handshake => {
+                    let frame = match self.next_frame()? {
+                        Some(frame) => frame,
+                        None => return Ok(None),
+                    };
+
+                    let metadata = match frame.as_frame().try_into_command() {
+                        Some(Ok(Command::Ready(metadata))) => metadata,
+                        Some(Ok(Command::Error(reason))) => {
+                            return Err(PeerError::new(reason).into())
+                        }
+                        Some(Err(_)) => return Err(self.reject("malformed READY command")),
+                        _ => return Err(self.reject("expected a READY command")),
+                    };
+
+                    let compatible = matches!(
+                        metadata.socket_type(),
+                        Some(peer) if compatible(self.socket_type, peer)
+                    );
+
+                    if !compatible {
+                        return Err(self.reject("invalid socket type"));
+                    }
+
+                    self.peer = Some(Arc::new(metadata));
+
+                    self.state = State::Ready;
+
+                    return Ok(Some(Event::Ready));
+                }
+
+                // Messages, and the commands in between them, are taken with `poll_into`.
+                State::Ready => return Ok(None),
+            }
+        }
+    }
+
+    /// Like [`Engine::poll`] but messages are decoded whole, straight into `message`.
+    ///
+    /// A message is only taken once all of its parts have been fed, its
+    /// parts then replace whatever `message` held. Reusing the same
+    /// `message` means receiving doesn't allocate once it's big enough.
+    /// Commands may arrive in between any two messages, those the engine
+    /// doesn't handle itself come out as [`Event::Command`].
+    ///
+    /// With the `bytes` feature the parts aren't copied, they're slices of
+    /// the input buffer.
+    pub fn poll_into(&mut self, message: &mut MessageBuf) -> io::Result<Option<Event>> {
+        self.poll_into_filtered(message, &mut |_| true)
+    }
+
+    /// Like [`Engine::poll_into`] but only messages whose first part `accept`s are taken.
+    ///
+    /// The others are thrown away before any of their parts are copied.
+    /// When conflating, so are the ones with a newer message behind them.
+    pub fn poll_into_filtered(
+        &mut self,
+        message: &mut MessageBuf,
+        accept: &mut dyn FnMut(&[u8]) -> bool,
+    ) -> io::Result<Option<Event>> {
+        if self.state != State::Ready {
+            return self.poll();
+        }
+
+        let end = loop {
+            self.check_size()?;
+
+            let pending = self.pending();
+            let mut end = 0;
+
+            let command = loop {
+                let frame = match Frame::parse(&pending[end..])? {
+                    Some(frame) => frame,
+                    None => return Ok(None),
+                };
+
+                match frame.kind() {
+                    Some(FrameKind::Command) if end == 0 => break true,
+
+                    Some(FrameKind::MessageTail) => {
+                        end += frame.bytes.len();
+                        break false;
+                    }
+
+                    _ => end += frame.bytes.len(),
+                }
+            };
+
+            if !command {
+                let head = Frame::parse(pending)?.and_then(|frame| frame.body());
+
+                if accept(head.unwrap_or_default())
+                    && !(self.conflate && any_accepted(frames(&pending[end..]), accept))
+                {
+                    break end;
+                }
+
+                self.take(end);
+                self.streamed = 0;
+                continue;
+            }
+
+            if let Some(frame) = self.next_frame()? {
+                if let Some(event) = self.command(&frame)? {
+                    return Ok(Some(event));
+                }
+            }
         };
 
-        // Send partial greeting
-        self.transport.write(&partial)?;
+        self.take_message(end, message)?;
+        self.streamed = 0;
 
-        // Inspect remote partial greeting.
-        {
-            let mut buf = [0u8; 12];
-            let n = self.transport.read(&mut buf)?;
-            assert_eq!(n, 12, "{:?}", buf);
+        Ok(Some(Event::Message))
+    }
 
-            // let peer_major = buf[10];
+    /// Copy the parts in the next `end` bytes of decoded input into `message`.
+    #[cfg(not(feature = "bytes"))]
+    fn take_message(&mut self, end: usize, message: &mut MessageBuf) -> io::Result<()> {
+        message.clear();
 
-            // if peer_major != major {
-            //     return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "peer major is not the same as us."))
-            // }
+        let mut bytes = self.take(end);
 
-            // let peer_minor = buf[11];
+        while let Some(frame) = Frame::parse(bytes)? {
+            bytes = &bytes[frame.bytes.len()..];
 
-            // if peer_minor > minor {
-            //     return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "peer minor is higher than us."))
-            // }
+            // Commands in between the parts of a message are ignored.
+            if let Some(part) = frame.try_into_part() {
+                message.push(part.body());
+            }
         }
 
-        // Send remaining greeting
-        self.transport.write(&remaining)?;
+        Ok(())
+    }
+
+    /// Split the next `end` bytes off of the input and hand out the parts in there as slices of it.
+    #[cfg(feature = "bytes")]
+    fn take_message(&mut self, end: usize, message: &mut MessageBuf) -> io::Result<()> {
+        message.clear();
+
+        self.input.advance(self.cursor);
+        self.filled -= self.cursor + end;
+        self.cursor = 0;
+
+        let mut bytes = self.input.split_to(end).freeze();
+
+        while let Some(frame) = Frame::parse(&bytes)? {
+            let length = frame.bytes.len();
+
+            // Commands in between the parts of a message are ignored.
+            let body = frame.try_into_part().map(|part| part.body().len());
+            let frame = bytes.split_to(length);
+
+            if let Some(body) = body {
+                message.push_bytes(frame.slice(length - body..));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Take just the flags and body size of the next frame.
+    ///
+    /// The body is left for the caller to read, first whatever of it is
+    /// buffered with [`Engine::read_body`] and then the rest straight from
+    /// the peer. Whatever the caller doesn't read has to be [`Engine::skip`]ped.
+    /// Commands are the exception, they're consumed whole and handled just
+    /// like [`Engine::poll_into`] does.
+    pub fn poll_header(&mut self) -> io::Result<Option<Event>> {
+        debug_assert!(self.is_ready());
+
+        loop {
+            match self.poll_command_header()? {
+                Some(Event::Header(FrameKind::Command, _)) => {
+                    if let Some(frame) = self.next_frame()? {
+                        if let Some(event) = self.command(&frame)? {
+                            return Ok(Some(event));
+                        }
+
+                        continue;
+                    }
+
+                    return Ok(None);
+                }
+
+                event => return Ok(event),
+            }
+        }
+    }
+
+    /// Take the header of the next frame, unless it's a command which is left for `next_frame`.
+    fn poll_command_header(&mut self) -> io::Result<Option<Event>> {
+        self.check_size()?;
+
+        let pending = self.pending();
+
+        let octets = match pending.first() {
+            Some(flags) => super::size_octets(*flags)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?,
+            None => return Ok(None),
+        };
+
+        let header = match pending.get(..1 + octets) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let size = match header[1..].try_into() {
+            Ok(long) => u64::from_be_bytes(long),
+            Err(_) => header[1] as u64,
+        };
+
+        let kind = Frame::new(header)
+            .kind()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+
+        // Commands are small, they're taken whole.
+        if kind == FrameKind::Command {
+            return Ok(Some(Event::Header(kind, size)));
+        }
+
+        self.take(1 + octets);
+
+        self.streamed = match kind {
+            FrameKind::MessagePart => self.streamed.saturating_add(size),
+            _ => 0,
+        };
+
+        Ok(Some(Event::Header(kind, size)))
+    }
+
+    /// Copy as much of the buffered input as fits into `buf`, returning how much that was.
+    pub fn read_body(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.pending().len());
+        buf[..n].copy_from_slice(self.take(n));
+        n
+    }
+
+    /// Throw away the next `n` bytes of input, including those that have yet to be fed.
+    pub fn skip(&mut self, n: u64) {
+        self.skip += n;
+        self.discard();
+    }
 
-        Ok(self)
+    /// Throw away as much of what's being skipped as is buffered.
+    fn discard(&mut self) {
+        let n = self.skip.min(self.pending().len() as u64);
+
+        self.cursor += n as usize;
+        self.skip -= n;
     }
 
-    pub fn ready<'b>(self, socket_type: &'b str) -> io::Result<Transport> {
-        let Self { mut transport, .. } = self;
+    /// Fail if the message at the front of the input is over the max message size.
+    ///
+    /// This goes by the frame headers alone so that an oversized message
+    /// is caught before any room is made for it.
+    fn check_size(&self) -> io::Result<()> {
+        let max = match self.max_msg_size {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+
+        let pending = self.pending();
+        let mut offset = 0;
+        let mut total = self.streamed;
+
+        while let Some(flags) = pending.get(offset) {
+            let octets = match super::size_octets(*flags) {
+                Some(octets) => octets,
+                None => break,
+            };
+
+            let header = match pending.get(offset + 1..offset + 1 + octets) {
+                Some(header) => header,
+                None => break,
+            };
 
-        {
-            // Read the remaining remote greeting.
-            let mut buf = [0u8; 52];
-            let n = transport.read(&mut buf[..])?;
-            assert_eq!(n, 52);
-            // TODO: parse, this contains the security mechanism (by default NULL) and some extra metadata.
+            let size = match header.try_into() {
+                Ok(long) => u64::from_be_bytes(long),
+                Err(_) => header[0] as u64,
+            };
 
-            // Inspect remote handshake.
-            let mut buf = [0u8; 64];
-            let _n = transport.read(&mut buf)?;
+            total = total.saturating_add(size);
 
-            // dbg!((super::Frame { bytes: &buf[..n] }.try_into_command()).unwrap());
+            if total > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "peer sent a message over the max message size of {} bytes",
+                        max
+                    ),
+                ));
+            }
 
-            // TODO: validate handshake, this contains (for NULL security mechanisms) the following properties:
-            //  - Socket-Type {type} i.e. PUSH, PULL, DEALER, ROUTER, PAIR
-            //  - Identity; only if WE are ROUTER and they are using a ROUTER compatible socket type with a custom routing id.
+            // The next header can only be had by skipping over a complete frame.
+            offset = match usize::try_from(size)
+                .ok()
+                .and_then(|size| (offset + 1 + octets).checked_add(size))
+            {
+                Some(next) if flags & 0x1 == 0x1 && next < pending.len() => next,
+                _ => break,
+            };
         }
 
-        // Send handshake
+        Ok(())
+    }
+
+    /// Handle a command the peer sent after the handshake.
+    ///
+    /// PINGs are answered straight away, PONGs and unknown commands are
+    /// ignored and an ERROR ends the connection. What's left is up to the
+    /// socket type and comes out as an [`Event::Command`].
+    fn command(&mut self, frame: &FrameBuf) -> io::Result<Option<Event>> {
+        let command = match frame.as_frame().try_into_command() {
+            Some(command) => command?,
+            None => return Ok(None),
+        };
+
+        match command {
+            Command::Ping { mut context, .. } => {
+                context.truncate(MAX_PING_CONTEXT);
+
+                let pong = Command::Pong(context).to_frame()?;
+                self.output.extend_from_slice(pong.as_ref());
+                Ok(None)
+            }
 
-        let handshake = {
-            let properties = vec![("Socket-Type", socket_type)];
+            Command::Pong(_) | Command::Unknown { .. } => Ok(None),
 
-            FrameBuf::short_command("READY", Some(properties))
+            Command::Error(reason) => Err(PeerError::new(reason).into()),
+
+            Command::Subscribe(_) | Command::Cancel(_) | Command::Join(_) | Command::Leave(_) => {
+                Ok(Some(Event::Command(command)))
+            }
+
+            Command::Ready(_) | Command::Hello { .. } | Command::Welcome | Command::Initiate(_) => {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("peer sent a {} command after the handshake", command.name()),
+                ))
+            }
+        }
+    }
+
+    /// Turn the peer away, queueing up an ERROR command that tells it why.
+    fn reject(&mut self, reason: &str) -> io::Error {
+        if let Ok(frame) = Command::Error(reason.to_string()).to_frame() {
+            self.output.extend_from_slice(frame.as_ref());
+        }
+
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("rejected the peer: {}", reason),
+        )
+    }
+
+    /// Decode the next complete frame out of the buffered input.
+    fn next_frame(&mut self) -> io::Result<Option<FrameBuf>> {
+        self.check_size()?;
+
+        let length = match Frame::parse(self.pending())? {
+            Some(frame) => frame.bytes.len(),
+            None => return Ok(None),
         };
 
-        transport.write(handshake.as_ref())?;
+        let frame_buf = FrameBuf::new(self.take(length).to_vec());
+
+        debug_assert!(frame_buf.as_frame().kind().is_some());
+
+        Ok(Some(frame_buf))
+    }
+}
+
+/// Whether sockets of these types may talk to each other.
+fn compatible(ours: &str, peer: &str) -> bool {
+    let peers: &[&str] = match ours {
+        "REQ" => &["REP", "ROUTER"],
+        "REP" => &["REQ", "DEALER"],
+        "DEALER" => &["REP", "DEALER", "ROUTER"],
+        "ROUTER" => &["REQ", "DEALER", "ROUTER"],
+        "PUB" | "XPUB" => &["SUB", "XSUB"],
+        "SUB" | "XSUB" => &["PUB", "XPUB"],
+        "PUSH" => &["PULL"],
+        "PULL" => &["PUSH"],
+        "PAIR" => &["PAIR"],
+        "RADIO" => &["DISH"],
+        "DISH" => &["RADIO"],
+        _ => &[],
+    };
+
+    peers.iter().any(|name| name.eq_ignore_ascii_case(peer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SocketOption;
+
+    /// The greeting of a ZMTP 3.1 peer using the NULL mechanism.
+    fn greeting() -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0x7F, 3, 1];
+        bytes.extend_from_slice(b"NULL");
+        bytes.resize(64, 0);
+        bytes
+    }
+
+    /// The READY command frame of a `socket_type` peer.
+    fn ready(socket_type: &str) -> Vec<u8> {
+        let properties = vec![("Socket-Type".to_string(), socket_type.as_bytes().to_vec())];
+        let frame = Command::Ready(Metadata::new(properties))
+            .to_frame()
+            .unwrap();
+        frame.as_ref().to_vec()
+    }
+
+    /// An engine that went through the handshake with a `peer` socket.
+    fn connected(socket_type: &'static str, peer: &str, options: &SocketOptions) -> Engine {
+        let mut engine = Engine::new(socket_type, options);
+
+        engine.feed(&greeting());
+        engine.feed(&ready(peer));
+
+        while !engine.is_ready() {
+            engine.poll().unwrap();
+        }
+
+        engine.consume_output(engine.output().len());
+        engine
+    }
+
+    #[test]
+    fn handshake_goes_through_every_state() {
+        let mut engine = Engine::new("PULL", &SocketOptions::default());
+
+        assert_eq!(engine.state, State::Signature);
+        assert_eq!(engine.output(), &greeting()[..PARTIAL_GREETING]);
+        engine.consume_output(PARTIAL_GREETING);
+
+        let greeting = greeting();
+
+        engine.feed(&greeting[..PARTIAL_GREETING]);
+        assert!(engine.poll().unwrap().is_none());
+        assert_eq!(engine.state, State::Greeting);
+        assert_eq!(engine.output(), &greeting[PARTIAL_GREETING..]);
+        engine.consume_output(REMAINING_GREETING);
+
+        engine.feed(&greeting[PARTIAL_GREETING..]);
+        assert!(engine.poll().unwrap().is_none());
+        assert_eq!(engine.state, State::Handshake);
+        assert_eq!(engine.output(), &ready("PULL")[..]);
+
+        engine.feed(&ready("PUSH"));
+        assert!(matches!(engine.poll().unwrap(), Some(Event::Ready)));
+        assert!(engine.is_ready());
+        assert_eq!(engine.peer().unwrap().socket_type(), Some("PUSH"));
+    }
+
+    #[test]
+    fn invalid_signatures_are_refused() {
+        let mut engine = Engine::new("PULL", &SocketOptions::default());
+        let mut greeting = greeting();
+        greeting[0] = 0;
+
+        engine.feed(&greeting);
+
+        let err = engine.poll().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn older_versions_are_refused() {
+        let mut engine = Engine::new("PULL", &SocketOptions::default());
+        let mut greeting = greeting();
+        greeting[10] = 2;
+
+        engine.feed(&greeting);
+
+        assert!(engine.poll().is_err());
+    }
+
+    #[test]
+    fn other_mechanisms_are_refused() {
+        let mut engine = Engine::new("PULL", &SocketOptions::default());
+        let mut greeting = greeting();
+        greeting[12..17].copy_from_slice(b"PLAIN");
+
+        engine.feed(&greeting);
+
+        let err = engine.poll().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(engine.output().ends_with(b"unsupported security mechanism"));
+    }
+
+    #[test]
+    fn incompatible_peers_are_sent_an_error() {
+        let mut engine = Engine::new("PULL", &SocketOptions::default());
+
+        engine.feed(&greeting());
+        engine.feed(&ready("PUB"));
+
+        let err = loop {
+            match engine.poll() {
+                Ok(_) => continue,
+                Err(err) => break err,
+            }
+        };
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(engine
+            .output()
+            .ends_with(b"\x04\x1A\x05ERROR\x13invalid socket type"));
+    }
+
+    #[test]
+    fn fed_a_byte_at_a_time() {
+        let mut bytes = greeting();
+        bytes.extend_from_slice(&ready("PUSH"));
+        bytes.extend_from_slice(b"\x04\x09\x04PING\x00\x0Ahi");
+        bytes.extend_from_slice(b"\x01\x01a\x04\x04\x03FOO\x00\x02bc");
+
+        let mut engine = Engine::new("PULL", &SocketOptions::default());
+        let mut message = MessageBuf::new();
+        let mut events = vec![];
+
+        for byte in bytes {
+            assert!(engine.wants() > 0);
+
+            engine.feed(&[byte]);
+
+            while let Some(event) = engine.poll_into(&mut message).unwrap() {
+                events.push(event);
+            }
+        }
+
+        assert!(matches!(events[..], [Event::Ready, Event::Message]));
+        assert_eq!(message, vec![b"a".to_vec(), b"bc".to_vec()]);
+        assert!(engine.output().ends_with(b"\x04\x07\x04PONGhi"));
+    }
+
+    #[test]
+    fn wants_the_rest_of_the_greeting() {
+        let mut engine = Engine::new("PULL", &SocketOptions::default());
+        assert_eq!(engine.wants(), PARTIAL_GREETING);
+
+        engine.feed(&greeting()[..5]);
+        assert_eq!(engine.wants(), PARTIAL_GREETING - 5);
+
+        engine.feed(&greeting()[5..PARTIAL_GREETING]);
+        engine.poll().unwrap();
+        assert_eq!(engine.wants(), REMAINING_GREETING);
+    }
+
+    #[test]
+    fn wants_short_frames_whole() {
+        let mut engine = connected("PULL", "PUSH", &SocketOptions::default());
+        assert_eq!(engine.wants(), 1);
+
+        engine.feed(b"\x00");
+        assert_eq!(engine.wants(), 1);
+
+        engine.feed(b"\x05");
+        assert_eq!(engine.wants(), 5);
+
+        engine.feed(b"hel");
+        assert_eq!(engine.wants(), 2);
+
+        engine.feed(b"lo");
+        assert_eq!(engine.wants(), 0);
+    }
+
+    #[test]
+    fn wants_long_frames_whole() {
+        let mut engine = connected("PULL", "PUSH", &SocketOptions::default());
+
+        engine.feed(b"\x02\x00\x00");
+        assert_eq!(engine.wants(), 6);
+
+        engine.feed(&1000u64.to_be_bytes()[2..]);
+        assert_eq!(engine.wants(), 1000);
+
+        // Sizes the peer has no way of backing up don't overflow.
+        let mut engine = connected("PULL", "PUSH", &SocketOptions::default());
+
+        engine.feed(b"\x02");
+        engine.feed(&u64::MAX.to_be_bytes());
+        assert_eq!(engine.wants(), usize::MAX - 9);
+    }
+
+    #[test]
+    fn reads_grow_with_what_came_in() {
+        let mut engine = connected("PULL", "PUSH", &SocketOptions::default());
+        assert_eq!(engine.read_size(), READ_CHUNK);
+
+        // However big the frame claims to be, room is only made as its bytes arrive.
+        engine.feed(b"\x02");
+        engine.feed(&u64::MAX.to_be_bytes());
+        assert_eq!(engine.read_size(), READ_CHUNK);
+
+        engine.feed(&vec![0; 4 * READ_CHUNK]);
+        assert_eq!(engine.read_size(), 4 * READ_CHUNK + 9);
+
+        // Once the rest of the frame is less than that, that's all there's room made for.
+        let mut engine = connected("PULL", "PUSH", &SocketOptions::default());
+        let size = 4 * READ_CHUNK as u64;
+
+        engine.feed(b"\x02");
+        engine.feed(&size.to_be_bytes());
+        engine.feed(&vec![0; 3 * READ_CHUNK]);
+        assert_eq!(engine.read_size(), READ_CHUNK);
+    }
+
+    #[test]
+    fn wants_every_part_of_a_message() {
+        let mut engine = connected("PULL", "PUSH", &SocketOptions::default());
+
+        engine.feed(b"\x01\x01a\x01\x01b");
+        assert_eq!(engine.wants(), 1);
+
+        engine.feed(b"\x00\x01c");
+        assert_eq!(engine.wants(), 0);
+    }
+
+    #[test]
+    fn output_is_consumed_in_pieces() {
+        let options = SocketOptions::default().with(SocketOption::SndHwm(1));
+        let mut engine = connected("PUSH", "PULL", &options);
+
+        engine
+            .write_message(MessageBuf::from("hello").as_message())
+            .unwrap();
+        assert_eq!(engine.output(), b"\x00\x05hello");
+        assert!(!engine.is_writable());
+
+        let err = engine
+            .write_message(MessageBuf::from("again").as_message())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        engine.consume_output(3);
+        assert_eq!(engine.output(), b"ello");
+        assert!(!engine.is_writable());
+
+        engine.consume_output(4);
+        assert!(engine.output().is_empty());
+        assert!(engine.is_writable());
+    }
+
+    #[test]
+    fn publishers_drop_at_the_hwm() {
+        let options = SocketOptions::default().with(SocketOption::SndHwm(1));
+        let mut engine = connected("PUB", "SUB", &options);
+
+        engine
+            .write_message(MessageBuf::from("a").as_message())
+            .unwrap();
+        engine
+            .write_message(MessageBuf::from("b").as_message())
+            .unwrap();
 
-        Ok(transport)
+        assert_eq!(engine.output(), b"\x00\x01a");
     }
 }
@@ -0,0 +1,68 @@
+use std::{error::Error, fmt, io};
+
+// -- PeerError
+
+/// The peer sent an ERROR command, e.g. because it rejected our handshake.
+///
+/// It comes wrapped in an [`io::Error`] of kind `ConnectionAborted`, the
+/// peer's reason can be had with [`PeerError::from_io`], even once a
+/// [`ReconnectPolicy`](crate::ReconnectPolicy) gave up because of it:
+///
+/// ```rust
+/// use std::io;
+/// use zedmq::PeerError;
+///
+/// let err = io::Error::from(PeerError::new("invalid socket type"));
+///
+/// assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+/// assert_eq!(PeerError::from_io(&err).map(PeerError::reason), Some("invalid socket type"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerError {
+    reason: String,
+}
+
+impl PeerError {
+    /// An error with the reason the peer gave.
+    pub fn new<R: Into<String>>(reason: R) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+
+    /// The reason the peer gave for closing the connection.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The `PeerError` inside of `err`, or anywhere down its chain of sources.
+    pub fn from_io(err: &io::Error) -> Option<&Self> {
+        let mut inner: &(dyn Error + 'static) = err.get_ref()?;
+
+        loop {
+            if let Some(err) = inner.downcast_ref() {
+                return Some(err);
+            }
+
+            // The `source` of an `io::Error` skips over the error it wraps, so look inside instead.
+            inner = match inner.downcast_ref::<io::Error>() {
+                Some(err) => err.get_ref()?,
+                None => inner.source()?,
+            };
+        }
+    }
+}
+
+impl fmt::Display for PeerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "peer closed the connection: {}", self.reason)
+    }
+}
+
+impl Error for PeerError {}
+
+impl From<PeerError> for io::Error {
+    fn from(err: PeerError) -> Self {
+        io::Error::new(io::ErrorKind::ConnectionAborted, err)
+    }
+}
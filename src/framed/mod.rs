@@ -0,0 +1,132 @@
+//! Runtime-agnostic sockets over any `AsyncRead + AsyncWrite` byte stream.
+//!
+//! Receiving sockets implement [`futures::Stream`] and sending sockets
+//! implement [`futures::Sink`] so they work with any executor (async-std,
+//! smol, tokio through a compat layer...) and compose with the usual
+//! stream combinators.
+//!
+//! Unlike the blocking and `aio` sockets these don't own the
+//! connection process, the caller hands over an already connected byte
+//! stream and the ZMTP handshake is performed on it. Consequently there is
+//! no reconnection, the stream simply ends when the peer goes away.
+//!
+//! Options are set the same way as for every other socket, through
+//! [`socket`](crate::socket) and [`SocketBuilder::handshake`](crate::SocketBuilder::handshake).
+//! `new` uses the defaults.
+//!
+//! Messages aren't queued up ahead of time, the byte stream is only read
+//! from while a receiving socket is polled and nothing complete is left
+//! buffered, or to find the latest message when conflating. Like for every
+//! other socket RCVHWM has no effect, see
+//! [`SocketOption::RcvHwm`](crate::SocketOption::RcvHwm).
+//!
+//! ```rust,no_run
+//! use futures::StreamExt;
+//! use zedmq::framed::Pull;
+//!
+//! # async fn example<T>(io: T) -> std::io::Result<()>
+//! # where T: futures::io::AsyncRead + futures::io::AsyncWrite + Unpin {
+//! let mut socket = Pull::new(io).await?;
+//!
+//! while let Some(message) = socket.next().await {
+//!     dbg!(message?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod pub_t;
+mod pull_t;
+mod push_t;
+mod rep_t;
+mod req_t;
+mod stream;
+mod sub_t;
+
+pub use self::{
+    pub_t::Pub,
+    pull_t::Pull,
+    push_t::Push,
+    rep_t::{Rep, RepPending},
+    req_t::{Req, ReqPending},
+    sub_t::Sub,
+};
+
+pub(crate) use self::stream::Framed;
+
+crate::socket_type::impl_options![
+    Pull<T>,
+    Push<T>,
+    Sub<T>,
+    Pub<T>,
+    Req<T>,
+    ReqPending<T>,
+    Rep<T>,
+    RepPending<T>
+];
+
+mod sealed {
+    /// Socket types that are made out of a [`Framed`](super::Framed) once the handshake is done.
+    pub trait FromFramed<T> {
+        fn from_framed(inner: super::Framed<T>) -> Self;
+    }
+}
+
+pub(crate) use sealed::FromFramed;
+
+/// Implement `FromFramed` for socket types that only wrap a `Framed<T>` in their `inner` field.
+macro_rules! impl_from_framed {
+    [$( $name:ident ),+] => {
+        $(
+            impl<T> $crate::framed::FromFramed<T> for $name<T> {
+                fn from_framed(inner: $crate::framed::Framed<T>) -> Self {
+                    Self { inner }
+                }
+            }
+        )+
+    };
+}
+
+pub(crate) use impl_from_framed;
+
+/// Implement `futures::Sink` for a socket type wrapping a `Framed<T>` in its `inner` field.
+///
+/// Anything that converts into a `MessageBuf` can be sent.
+macro_rules! impl_sink {
+    ($name:ident) => {
+        impl<T, M> ::futures::Sink<M> for $name<T>
+        where
+            T: ::futures::io::AsyncRead + ::futures::io::AsyncWrite + Unpin,
+            M: Into<$crate::MessageBuf>,
+        {
+            type Error = ::std::io::Error;
+
+            fn poll_ready(
+                self: ::std::pin::Pin<&mut Self>,
+                cx: &mut ::std::task::Context<'_>,
+            ) -> ::std::task::Poll<Result<(), Self::Error>> {
+                self.get_mut().inner.poll_ready(cx)
+            }
+
+            fn start_send(self: ::std::pin::Pin<&mut Self>, item: M) -> Result<(), Self::Error> {
+                self.get_mut().inner.start_send(item.into().as_message())
+            }
+
+            fn poll_flush(
+                self: ::std::pin::Pin<&mut Self>,
+                cx: &mut ::std::task::Context<'_>,
+            ) -> ::std::task::Poll<Result<(), Self::Error>> {
+                self.get_mut().inner.poll_flush(cx)
+            }
+
+            fn poll_close(
+                self: ::std::pin::Pin<&mut Self>,
+                cx: &mut ::std::task::Context<'_>,
+            ) -> ::std::task::Poll<Result<(), Self::Error>> {
+                self.get_mut().inner.poll_close(cx)
+            }
+        }
+    };
+}
+
+pub(crate) use impl_sink;
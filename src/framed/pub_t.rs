@@ -0,0 +1,28 @@
+use std::io;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{impl_from_framed, impl_sink, Framed};
+
+/// A zmq PUB socket over any `AsyncRead + AsyncWrite` byte stream.
+///
+/// We're on the connected end of the session so we rely on the
+/// subscriber in order to perform filtering.
+#[derive(Debug)]
+pub struct Pub<T> {
+    pub(super) inner: Framed<T>,
+}
+
+impl<T> Pub<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Perform the ZMTP handshake over `io` and produce a PUB socket with the default options.
+    pub async fn new(io: T) -> io::Result<Self> {
+        crate::socket().handshake(io).await
+    }
+}
+
+impl_sink!(Pub);
+
+impl_from_framed![Pub];
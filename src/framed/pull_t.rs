@@ -0,0 +1,39 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{impl_from_framed, Framed};
+use crate::MessageBuf;
+
+/// A zmq PULL socket over any `AsyncRead + AsyncWrite` byte stream.
+#[derive(Debug)]
+pub struct Pull<T> {
+    pub(super) inner: Framed<T>,
+}
+
+impl<T> Pull<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Perform the ZMTP handshake over `io` and produce a PULL socket with the default options.
+    pub async fn new(io: T) -> io::Result<Self> {
+        crate::socket().handshake(io).await
+    }
+}
+
+impl<T> futures::Stream for Pull<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = io::Result<MessageBuf>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().inner.poll_message(cx, &mut ())
+    }
+}
+
+impl_from_framed![Pull];
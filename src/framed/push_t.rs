@@ -0,0 +1,25 @@
+use std::io;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{impl_from_framed, impl_sink, Framed};
+
+/// A zmq PUSH socket over any `AsyncRead + AsyncWrite` byte stream.
+#[derive(Debug)]
+pub struct Push<T> {
+    pub(super) inner: Framed<T>,
+}
+
+impl<T> Push<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Perform the ZMTP handshake over `io` and produce a PUSH socket with the default options.
+    pub async fn new(io: T) -> io::Result<Self> {
+        crate::socket().handshake(io).await
+    }
+}
+
+impl_sink!(Push);
+
+impl_from_framed![Push];
@@ -0,0 +1,55 @@
+use std::io;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{impl_from_framed, Framed};
+use crate::MessageBuf;
+
+// -- RepPending
+
+/// A zmq REP socket pending a response.
+#[derive(Debug)]
+pub struct RepPending<T> {
+    pub(super) inner: Framed<T>,
+}
+
+impl<T> RepPending<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Send a message.
+    pub async fn send<M>(mut self, message: M) -> io::Result<Rep<T>>
+    where
+        M: Into<MessageBuf>,
+    {
+        self.inner.send(message.into().as_message()).await?;
+        Ok(Rep { inner: self.inner })
+    }
+}
+
+// -- Rep
+
+/// A zmq REP socket over any `AsyncRead + AsyncWrite` byte stream.
+#[derive(Debug)]
+pub struct Rep<T> {
+    pub(super) inner: Framed<T>,
+}
+
+impl<T> Rep<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Perform the ZMTP handshake over `io` and produce a REP socket with the default options.
+    pub async fn new(io: T) -> io::Result<Self> {
+        crate::socket().handshake(io).await
+    }
+
+    /// Recieve a multipart message with the pending REP socket.
+    pub async fn recv(mut self) -> io::Result<(MessageBuf, RepPending<T>)> {
+        let data = self.inner.recv().await?;
+        let Self { inner } = self;
+        Ok((data, RepPending { inner }))
+    }
+}
+
+impl_from_framed![Rep];
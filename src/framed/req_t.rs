@@ -0,0 +1,55 @@
+use std::io;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{impl_from_framed, Framed};
+use crate::MessageBuf;
+
+// -- ReqPending
+
+/// A zmq REQ socket pending a response.
+#[derive(Debug)]
+pub struct ReqPending<T> {
+    pub(super) inner: Framed<T>,
+}
+
+impl<T> ReqPending<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// recieve a message.
+    pub async fn recv(mut self) -> io::Result<(MessageBuf, Req<T>)> {
+        let data = self.inner.recv().await?;
+        let Self { inner } = self;
+        Ok((data, Req { inner }))
+    }
+}
+
+// -- Req
+
+/// A zmq REQ socket over any `AsyncRead + AsyncWrite` byte stream.
+#[derive(Debug)]
+pub struct Req<T> {
+    pub(super) inner: Framed<T>,
+}
+
+impl<T> Req<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Perform the ZMTP handshake over `io` and produce a REQ socket with the default options.
+    pub async fn new(io: T) -> io::Result<Self> {
+        crate::socket().handshake(io).await
+    }
+
+    /// Send a message.
+    pub async fn send<M>(mut self, message: M) -> io::Result<ReqPending<T>>
+    where
+        M: Into<MessageBuf>,
+    {
+        self.inner.send(message.into().as_message()).await?;
+        Ok(ReqPending { inner: self.inner })
+    }
+}
+
+impl_from_framed![Req];
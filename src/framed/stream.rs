@@ -0,0 +1,234 @@
+//! The shared machinery between all the framed socket types.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    io::{AsyncRead, AsyncWrite},
+    ready,
+};
+
+use crate::{
+    codec::{Engine, Event, Message, MessageBuf, READ_CHUNK},
+    socket_type::Handler,
+    stream::MAX_DRAIN,
+    SocketOptions,
+};
+
+// -- Framed<T>
+
+/// The shared machinery between all the framed socket types.
+#[derive(Debug)]
+pub struct Framed<T> {
+    io: T,
+    engine: Engine,
+    options: SocketOptions,
+}
+
+impl<T> Framed<T> {
+    /// The options this socket was configured with.
+    pub(crate) fn options(&self) -> &SocketOptions {
+        &self.options
+    }
+}
+
+impl<T> Framed<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Perform the ZMTP handshake over `io`.
+    pub(crate) async fn handshake(
+        io: T,
+        socket_type: &'static str,
+        options: SocketOptions,
+    ) -> io::Result<Self> {
+        options.check()?;
+
+        let mut framed = Self {
+            io,
+            engine: Engine::new(socket_type, &options),
+            options,
+        };
+
+        futures::future::poll_fn(|cx| framed.poll_handshake(cx)).await?;
+
+        Ok(framed)
+    }
+
+    fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            ready!(self.poll_flush(cx))?;
+
+            if self.engine.is_ready() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let event = match self.engine.poll() {
+                Ok(event) => event,
+                Err(err) => {
+                    // An ERROR may have been queued up to tell the peer why it's turned away.
+                    let _ = self.poll_flush(cx);
+                    return Poll::Ready(Err(err));
+                }
+            };
+
+            // Only wait on the peer once everything it needs from us has been sent.
+            if event.is_none()
+                && self.engine.output().is_empty()
+                && ready!(self.poll_fill(cx))? == 0
+            {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+            }
+        }
+    }
+
+    /// Pull more bytes off of the underlying stream, `Ok(0)` signals EOF.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let size = self.engine.read_size();
+        let n = ready!(Pin::new(&mut self.io).poll_read(cx, self.engine.read_buf(size)))?;
+        self.engine.commit(n);
+        Poll::Ready(Ok(n))
+    }
+
+    /// Read whatever the peer sent so far into the engine without waiting, so the latest of it can be taken.
+    ///
+    /// A peer that sends faster than it can be drained gets cut off after `MAX_DRAIN` bytes.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let mut drained = 0;
+
+        while drained < MAX_DRAIN {
+            let read = Pin::new(&mut self.io).poll_read(cx, self.engine.read_buf(READ_CHUNK));
+
+            match read {
+                // The next fill finds out that the stream ended.
+                Poll::Ready(Ok(0)) | Poll::Pending => break,
+                Poll::Ready(Ok(n)) => {
+                    self.engine.commit(n);
+                    drained += n;
+                }
+                Poll::Ready(Err(err)) if err.kind() == io::ErrorKind::Interrupted => (),
+                Poll::Ready(Err(err)) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Poll for a complete multi-part message, commands in between are passed on to `handler`.
+    ///
+    /// Unless conflating, the stream is only read from once nothing complete is left in the
+    /// engine's input buffer.
+    pub(crate) fn poll_message(
+        &mut self,
+        cx: &mut Context<'_>,
+        handler: &mut dyn Handler,
+    ) -> Poll<Option<io::Result<MessageBuf>>> {
+        let mut message = MessageBuf::new();
+
+        loop {
+            if self.engine.conflates() {
+                self.poll_drain(cx)?;
+            }
+
+            let accept = &mut |head: &[u8]| handler.accepts(head);
+
+            match self.engine.poll_into_filtered(&mut message, accept)? {
+                Some(Event::Message) => {
+                    message.set_metadata(self.engine.peer().cloned());
+
+                    return Poll::Ready(Some(Ok(message)));
+                }
+
+                Some(Event::Command(command)) => {
+                    handler.command(command)?;
+                    continue;
+                }
+
+                _ => (),
+            }
+
+            // Answers to PINGs go out without holding up receiving.
+            if let Poll::Ready(Err(err)) = self.poll_flush(cx) {
+                return Poll::Ready(Some(Err(err)));
+            }
+
+            if ready!(self.poll_fill(cx))? == 0 {
+                // Running out mid-message (or mid-frame) is an error, in between messages it's the end.
+                if self.engine.is_idle() {
+                    return Poll::Ready(None);
+                }
+
+                return Poll::Ready(Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof))));
+            }
+        }
+    }
+
+    /// Subscribe the peer to `topic`, or with `subscribe` unset cancel that subscription.
+    pub(crate) async fn write_subscription(
+        &mut self,
+        topic: &[u8],
+        subscribe: bool,
+    ) -> io::Result<()> {
+        self.engine.write_subscription(topic, subscribe)?;
+        futures::future::poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Queue up a multi-part message for the peer.
+    ///
+    /// PUB sockets drop the message if the SNDHWM has been reached.
+    #[inline]
+    pub(crate) fn start_send(&mut self, message: Message<'_>) -> io::Result<()> {
+        self.engine.write_message(message)
+    }
+
+    /// Flush until there's room for another message below the SNDHWM.
+    ///
+    /// Sockets that drop messages at the high-water mark are always ready,
+    /// they only use this as an opportunity to make progress on the output.
+    pub(crate) fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.engine.is_writable() {
+            return Poll::Ready(Ok(()));
+        }
+
+        match self.poll_flush(cx) {
+            Poll::Pending if self.engine.drops_at_hwm() => Poll::Ready(Ok(())),
+            polled => polled,
+        }
+    }
+
+    pub(crate) fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.engine.output().is_empty() {
+            let n = ready!(Pin::new(&mut self.io).poll_write(cx, self.engine.output()))?;
+
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero)));
+            }
+
+            self.engine.consume_output(n);
+        }
+
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    pub(crate) fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_flush(cx))?;
+        Pin::new(&mut self.io).poll_close(cx)
+    }
+
+    /// Send a message and wait for it to be flushed.
+    pub(crate) async fn send(&mut self, message: Message<'_>) -> io::Result<()> {
+        futures::future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        self.start_send(message)?;
+        futures::future::poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Wait for the next message, running out of messages is an error.
+    pub(crate) async fn recv(&mut self) -> io::Result<MessageBuf> {
+        futures::future::poll_fn(|cx| self.poll_message(cx, &mut ()))
+            .await
+            .unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::UnexpectedEof)))
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{Framed, FromFramed};
+use crate::socket_type::{
+    sub_t::{Filters, Matching},
+    trie::Trie,
+};
+use crate::MessageBuf;
+
+/// A ZMQ SUB socket over any `AsyncRead + AsyncWrite` byte stream.
+///
+/// As a `Stream` it only yields messages that match a subscribed topic prefix and the filters.
+#[derive(Debug)]
+pub struct Sub<T> {
+    pub(super) inner: Framed<T>,
+    topics: Trie,
+    filters: Filters,
+}
+
+impl<T> Sub<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Perform the ZMTP handshake over `io` and produce a SUB socket with the default options.
+    pub async fn new(io: T) -> io::Result<Self> {
+        crate::socket().handshake(io).await
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// Subscribing to a topic more than once only tells the publisher the first time, and takes as
+    /// many `unsubscribe`s to undo.
+    pub async fn subscribe(&mut self, topic: &[u8]) -> io::Result<()> {
+        // Note down the subscribing topic locally for prefix matching when receiving.
+        match self.topics.add(topic) {
+            true => self.inner.write_subscription(topic, true).await,
+            false => Ok(()),
+        }
+    }
+
+    /// Only yield messages whose first frame satisfies `predicate`, on top of matching a subscription.
+    ///
+    /// See [`Sub::filter`](crate::Sub::filter) for details.
+    pub fn filter<F>(&mut self, predicate: F)
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.filters.push(predicate);
+    }
+
+    /// Unsubscribe from a topic, undoing one `subscribe` to it.
+    ///
+    /// Topics that were never subscribed to are ignored.
+    pub async fn unsubscribe(&mut self, topic: &[u8]) -> io::Result<()> {
+        match self.topics.remove(topic) {
+            true => self.inner.write_subscription(topic, false).await,
+            false => Ok(()),
+        }
+    }
+}
+
+impl<T> FromFramed<T> for Sub<T> {
+    fn from_framed(inner: Framed<T>) -> Self {
+        let filters = Filters::new(inner.options().invert_matching());
+
+        Self {
+            inner,
+            topics: Trie::default(),
+            filters,
+        }
+    }
+}
+
+impl<T> futures::Stream for Sub<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = io::Result<MessageBuf>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let topics = &this.topics;
+
+        let mut matching = Matching {
+            subscribed: &|head| topics.matches(head),
+            filters: &this.filters,
+        };
+
+        this.inner.poll_message(cx, &mut matching)
+    }
+}
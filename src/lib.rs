@@ -25,11 +25,29 @@
 //!
 //! #### Caveats
 //!
-//! Currently this library only supports connecting sockets
-//! over TCP, no binding behaviour is available.
+//! Sockets connect over TCP, only PUB sockets can also bind: they accept
+//! their peers and filter messages for them themselves, when sending or when
+//! `poll`ed. Sockets may also be started over any user supplied
+//! `Read + Write` byte stream with `from_stream`.
+//!
+//! There is no background I/O thread, messages queued up to the SNDHWM are
+//! written out as the socket gets used. Closing (or dropping) a socket
+//! flushes them for up to the `Linger` option, forever by default. Once the
+//! SNDHWM is reached PUB sockets drop messages while every other socket
+//! type blocks.
 //!
 //! Also only a few socket types have been implemented: REQ, REP, PULL, PUSH,
-//! and SUB (PUB is being worked on).
+//! SUB, and PUB.
+//!
+//! #### Async
+//!
+//! With the `tokio` feature enabled the [`aio`] module provides async
+//! versions of every socket type on top of tokio's `TcpStream` and
+//! `UnixStream`, sharing the same ZMTP codec as the blocking sockets.
+//!
+//! With the `futures` feature enabled the [`framed`] module provides
+//! runtime-agnostic sockets over any `AsyncRead + AsyncWrite` that implement
+//! `futures::Stream` (receivers) and `futures::Sink` (senders).
 //!
 //! #### `Frame<'_>` and `FrameBuf`
 //!
@@ -41,6 +59,23 @@
 //! the `FrameBuf` equivelent is `Vec<u8>` or `String`. This distinction is
 //! made in an attempt to make "zero copy" or "zero heap" practice easier.
 //!
+//! #### `Message<'_>` and `MessageBuf`
+//!
+//! Multipart messages follow the same pattern: a `MessageBuf` owns all of its
+//! parts in one buffer and a `Message<'_>` borrows one. Sending takes anything
+//! that converts into a `MessageBuf`, such as a `&str`, a `Vec<u8>`, a tuple or
+//! array of parts, or a `Vec<Vec<u8>>`. Received messages also carry the
+//! `Metadata` the peer sent during the handshake, such as its socket type.
+//!
+//! `recv_into` receives into an existing `MessageBuf` and hands back a
+//! `Message<'_>` borrowing it, reusing the same buffer across calls means
+//! receiving doesn't allocate.
+//!
+//! With the `bytes` feature enabled parts can also be `bytes::Bytes`, these
+//! are sent without being copied (handy when fanning one payload out to
+//! many peers or forwarding messages) and received messages are made of
+//! slices of the buffer they were read into rather than copies.
+//!
 //! #### `REQ` and `REP`
 //!
 //! The design of `REQ` and `REP` sockets are symetrical and rendered safe
@@ -79,18 +114,30 @@
 /// The currently supported ZMQ version.
 pub const ZMQ_VERSION: (u8, u8, u8) = (3, 1, 0);
 
+#[cfg(feature = "tokio")]
+pub mod aio;
 pub(crate) mod codec;
+mod error;
+#[cfg(feature = "futures")]
+pub mod framed;
+mod options;
+mod reconnect;
 mod socket_type;
 pub(crate) mod stream;
 
+pub use codec::{Message, MessageBuf, Metadata, Parts};
+pub use error::PeerError;
+pub use options::{Mechanism, SocketBuilder, SocketOption, SocketOptions};
+pub use reconnect::ReconnectPolicy;
 pub use socket_type::{
+    pub_t::Pub,
     pull_t::Pull,
     push_t::Push,
     rep_t::{Rep, RepPending},
     req_t::{Req, ReqPending},
     sub_t::Sub,
-    pub_t::Pub,
 };
+pub use stream::{FrameReader, MessageWriter};
 
 mod sealed {
     pub trait SocketType {
@@ -98,65 +145,226 @@ mod sealed {
     }
 
     macro_rules! impl_socket_type {
-        [$( ($name:ident, $st:literal) ),+] => {
+        [$( ($name:path, $st:literal) ),+] => {
             $(
-                impl SocketType for $crate::$name { fn name() -> &'static str { $st } }
+                impl SocketType for $name { fn name() -> &'static str { $st } }
             )+
-        }
+        };
+        [$( <$t:ident> ($name:ty, $st:literal) ),+] => {
+            $(
+                impl<$t> SocketType for $name { fn name() -> &'static str { $st } }
+            )+
+        };
     }
 
     impl_socket_type![
-        (Pull, "PULL"),
-        (Push, "PUSH"),
-        (Sub, "SUB"),
-        (Req, "REQ"),
-        (Rep, "REP"),
-        (Pub, "PUB")
+        (crate::Pull, "PULL"),
+        (crate::Push, "PUSH"),
+        (crate::Sub, "SUB"),
+        (crate::Req, "REQ"),
+        (crate::Rep, "REP"),
+        (crate::Pub, "PUB")
+    ];
+
+    #[cfg(feature = "tokio")]
+    impl_socket_type![
+        (crate::aio::Pull, "PULL"),
+        (crate::aio::Push, "PUSH"),
+        (crate::aio::Sub, "SUB"),
+        (crate::aio::Req, "REQ"),
+        (crate::aio::Rep, "REP"),
+        (crate::aio::Pub, "PUB")
+    ];
+
+    #[cfg(feature = "futures")]
+    impl_socket_type![
+        <T> (crate::framed::Pull<T>, "PULL"),
+        <T> (crate::framed::Push<T>, "PUSH"),
+        <T> (crate::framed::Sub<T>, "SUB"),
+        <T> (crate::framed::Req<T>, "REQ"),
+        <T> (crate::framed::Rep<T>, "REP"),
+        <T> (crate::framed::Pub<T>, "PUB")
     ];
 }
 
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::io::{self, Read, Write};
+
 use sealed::SocketType;
 use socket_type::Socket;
 use stream::Stream;
 
 /// All the supported transports that are supported i.e. tcp, udp.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 #[allow(missing_docs)]
 pub enum TransportKind {
     TCP,
+    IPC,
 }
 
-impl From<&str> for TransportKind {
-    fn from(st: &str) -> Self {
+impl TryFrom<&str> for TransportKind {
+    type Error = io::Error;
+
+    fn try_from(st: &str) -> io::Result<Self> {
         match st {
-            "tcp" => Self::TCP,
-            _ => unimplemented!("{:?}", st),
+            "tcp" => Ok(Self::TCP),
+            "ipc" => Ok(Self::IPC),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown transport {:?}", st),
+            )),
         }
     }
 }
 
+/// Turn `transport` into a `TransportKind`, unknown transports fail with `InvalidInput`.
+pub(crate) fn transport_kind<T>(transport: T) -> io::Result<TransportKind>
+where
+    T: TryInto<TransportKind>,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    transport
+        .try_into()
+        .map_err(|err| match err.into().downcast() {
+            Ok(err) => *err,
+            Err(err) => io::Error::new(io::ErrorKind::InvalidInput, err),
+        })
+}
+
+/// Fail with `Unsupported` unless `transport` is one the blocking sockets can use.
+fn blocking_transport(transport: TransportKind) -> io::Result<()> {
+    match transport {
+        TransportKind::TCP => Ok(()),
+        TransportKind::IPC => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only the async sockets support the ipc transport",
+        )),
+    }
+}
+
+/// Start building a socket of type `S` with custom options.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use zedmq::prelude::*;
+///
+/// fn main() -> std::io::Result<()> {
+///     let socket: Pull = zedmq::socket()
+///         .option(SocketOption::RcvHwm(10))
+///         .option(SocketOption::RcvTimeo(Some(Duration::from_secs(1))))
+///         .connect("tcp://127.0.0.1:5678")?;
+///
+///     assert_eq!(socket.options().rcvhwm(), 10);
+///
+///     Ok(())
+/// }
+/// ```
+pub fn socket<S>() -> SocketBuilder<S>
+where
+    S: SocketType,
+{
+    SocketBuilder::new()
+}
+
 /// Start a ZMQ socket with the specified `transport` to the specified `address`.
+///
+/// Connecting is retried according to the default [`ReconnectPolicy`],
+/// which is to say every 100 ms forever. Only `"tcp"` is supported, other
+/// transports fail with `Unsupported` and unknown ones with `InvalidInput`.
 pub fn connect<S, T>(transport: T, address: &str) -> std::io::Result<S>
 where
     S: SocketType + Socket + From<Stream>,
-    T: Into<TransportKind>,
+    T: TryInto<TransportKind>,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
 {
-    let transport = transport.into();
+    connect_with(transport, address, SocketOptions::default())
+}
 
-    assert_eq!(transport, TransportKind::TCP, "Only TCP is supported.");
+/// Like [`connect`] but with the socket configured by `options`.
+///
+/// `options` may also just be a [`ReconnectPolicy`]. If the policy gives up
+/// before the first connection is made the error of the last attempt is
+/// returned, the same goes for any later `send` or `recv` that has to
+/// reconnect.
+pub fn connect_with<S, T, O>(transport: T, address: &str, options: O) -> std::io::Result<S>
+where
+    S: SocketType + Socket + From<Stream>,
+    T: TryInto<TransportKind>,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    O: Into<SocketOptions>,
+{
+    blocking_transport(transport_kind(transport)?)?;
 
     let name = <S as SocketType>::name();
-    let stream = Stream::connected(name, address);
+    let stream = Stream::connected(name, address, options.into())?;
 
     Ok(stream.into())
 }
 
+/// Start a ZMQ socket over an already established, user supplied, byte stream.
+///
+/// This makes it possible to speak ZMTP over anything that is `Read + Write`
+/// e.g. SSH channels, serial links, pipes or already accepted sockets. The
+/// handshake is performed on `conn` before returning.
+///
+/// Since there is no way to produce a new `conn` the socket will error once
+/// the connection goes away, see [`from_stream_with`] for a reconnecting
+/// alternative.
+pub fn from_stream<S, C>(conn: C) -> std::io::Result<S>
+where
+    S: SocketType + Socket + From<Stream>,
+    C: Read + Write + Send + 'static,
+{
+    socket().from_stream(conn)
+}
+
+/// Like [`from_stream`] but with a `reconnect` closure that is called to produce
+/// a fresh connection whenever the current one goes away.
+pub fn from_stream_with<S, C, F>(conn: C, reconnect: F) -> std::io::Result<S>
+where
+    S: SocketType + Socket + From<Stream>,
+    C: Read + Write + Send + 'static,
+    F: FnMut() -> std::io::Result<C> + Send + 'static,
+{
+    socket().from_stream_with(conn, reconnect)
+}
+
 /// Bind a ZMQ socket with the specified `transport` to the specified `address`.
-pub fn bind<S>(_transport: &str, _address: &str) -> std::io::Result<S>
+///
+/// Only PUB sockets can be bound for now, and only over TCP. Every other
+/// socket type fails with `Unsupported`.
+pub fn bind<S, T>(transport: T, address: &str) -> std::io::Result<S>
+where
+    S: SocketType + Socket + From<Stream>,
+    T: TryInto<TransportKind>,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    bind_with(transport, address, SocketOptions::default())
+}
+
+/// Like [`bind`] but with the socket configured by `options`.
+pub fn bind_with<S, T, O>(transport: T, address: &str, options: O) -> std::io::Result<S>
 where
     S: SocketType + Socket + From<Stream>,
+    T: TryInto<TransportKind>,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    O: Into<SocketOptions>,
 {
-    unimplemented!();
+    blocking_transport(transport_kind(transport)?)?;
+
+    let name = <S as SocketType>::name();
+
+    if name != "PUB" {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("{} sockets can not be bound", name),
+        ));
+    }
+
+    let stream = Stream::bound(name, address, options.into())?;
+
+    Ok(stream.into())
 }
 
 /// The library prelude, containing all the stuff you probably want.
@@ -0,0 +1,380 @@
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+    time::Duration,
+};
+
+use crate::{
+    stream::{Connection, Factory},
+    ReconnectPolicy, Socket, SocketType, Stream,
+};
+
+/// The security mechanisms a socket may use.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Mechanism {
+    /// No security at all, the default.
+    #[default]
+    Null,
+}
+
+impl Mechanism {
+    /// The name the mechanism goes by in the greeting.
+    pub(crate) fn name(self) -> &'static [u8] {
+        match self {
+            Self::Null => b"NULL",
+        }
+    }
+}
+
+/// A single socket option, named after the ZMQ option it mirrors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketOption {
+    /// `ZMQ_SNDHWM`, the most messages that may be queued up for a peer.
+    SndHwm(usize),
+
+    /// `ZMQ_RCVHWM`, the most messages that may be queued up from a peer.
+    ///
+    /// This is accepted for compatibility but has no effect, messages from
+    /// a peer aren't queued up ahead of time. A receive only reads from the
+    /// peer once nothing complete is left buffered, and stops as soon as a
+    /// message is, so at most a read's worth (64 KiB) past it is held on to.
+    /// The rest waits in the connection, holding up the peer once it's full.
+    /// Conflating sockets are the exception, see [`SocketOption::Conflate`].
+    RcvHwm(usize),
+
+    /// `ZMQ_SNDTIMEO`, how long a send may block, `None` blocks forever.
+    SndTimeo(Option<Duration>),
+
+    /// `ZMQ_RCVTIMEO`, how long a receive may block, `None` blocks forever.
+    RcvTimeo(Option<Duration>),
+
+    /// `ZMQ_LINGER`, how long pending messages are flushed for on close, `None` waits forever.
+    Linger(Option<Duration>),
+
+    /// `ZMQ_RECONNECT_IVL` and friends, how lost connections are re-established.
+    Reconnect(ReconnectPolicy),
+
+    /// `ZMQ_HEARTBEAT_IVL`, how often to PING the peer, `None` disables heartbeats.
+    ///
+    /// Heartbeats aren't supported yet, building a socket with any of the
+    /// heartbeat options set to something fails with `Unsupported`.
+    HeartbeatIvl(Option<Duration>),
+
+    /// `ZMQ_HEARTBEAT_TTL`, how long the peer should wait on us before timing out.
+    ///
+    /// Not supported yet, see [`SocketOption::HeartbeatIvl`].
+    HeartbeatTtl(Option<Duration>),
+
+    /// `ZMQ_HEARTBEAT_TIMEOUT`, how long to wait on a PONG, `None` uses the heartbeat interval.
+    ///
+    /// Not supported yet, see [`SocketOption::HeartbeatIvl`].
+    HeartbeatTimeout(Option<Duration>),
+
+    /// `ZMQ_ROUTING_ID`, the identity announced to the peer during the handshake.
+    RoutingId(Vec<u8>),
+
+    /// `ZMQ_MAXMSGSIZE`, the largest inbound message accepted, `None` is unlimited.
+    ///
+    /// This is checked against frame headers as they come in, before any
+    /// room is made for the message. A peer sending a larger message (or
+    /// command) is disconnected and the receive fails with `InvalidData`.
+    MaxMsgSize(Option<u64>),
+
+    /// `ZMQ_MECHANISM`, the security mechanism to use, peers have to use the same one.
+    Mechanism(Mechanism),
+
+    /// `ZMQ_INVERT_MATCHING`, take the messages that match none of the subscriptions instead.
+    ///
+    /// This is for SUB and bound PUB sockets. A SUB socket still sends its
+    /// subscriptions as they are, so the publisher has to invert matching too.
+    InvertMatching(bool),
+
+    /// `ZMQ_CONFLATE`, only take the latest message that came in, for SUB and PULL sockets.
+    ///
+    /// Whatever came in before it is thrown away, so a receiver that fell
+    /// behind catches up straight away. To find the latest message a
+    /// receive first reads whatever the peer sent so far without waiting,
+    /// up to 1 MiB. Connections handed to `from_stream` can't be read
+    /// without waiting, there only what's already buffered is skipped.
+    Conflate(bool),
+}
+
+// -- SocketOptions
+
+/// The options a socket is configured with.
+///
+/// The defaults are the same as libzmq's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocketOptions {
+    sndhwm: usize,
+    rcvhwm: usize,
+    sndtimeo: Option<Duration>,
+    rcvtimeo: Option<Duration>,
+    linger: Option<Duration>,
+    reconnect: ReconnectPolicy,
+    heartbeat_ivl: Option<Duration>,
+    heartbeat_ttl: Option<Duration>,
+    heartbeat_timeout: Option<Duration>,
+    routing_id: Option<Vec<u8>>,
+    max_msg_size: Option<u64>,
+    mechanism: Mechanism,
+    invert_matching: bool,
+    conflate: bool,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            sndhwm: 1000,
+            rcvhwm: 1000,
+            sndtimeo: None,
+            rcvtimeo: None,
+            linger: None,
+            reconnect: ReconnectPolicy::default(),
+            heartbeat_ivl: None,
+            heartbeat_ttl: None,
+            heartbeat_timeout: None,
+            routing_id: None,
+            max_msg_size: None,
+            mechanism: Mechanism::default(),
+            invert_matching: false,
+            conflate: false,
+        }
+    }
+}
+
+impl From<ReconnectPolicy> for SocketOptions {
+    fn from(reconnect: ReconnectPolicy) -> Self {
+        Self::default().with(SocketOption::Reconnect(reconnect))
+    }
+}
+
+impl SocketOptions {
+    /// Set a single option.
+    pub fn with(mut self, option: SocketOption) -> Self {
+        match option {
+            SocketOption::SndHwm(hwm) => self.sndhwm = hwm,
+            SocketOption::RcvHwm(hwm) => self.rcvhwm = hwm,
+            SocketOption::SndTimeo(timeout) => self.sndtimeo = timeout,
+            SocketOption::RcvTimeo(timeout) => self.rcvtimeo = timeout,
+            SocketOption::Linger(linger) => self.linger = linger,
+            SocketOption::Reconnect(reconnect) => self.reconnect = reconnect,
+            SocketOption::HeartbeatIvl(ivl) => self.heartbeat_ivl = ivl,
+            SocketOption::HeartbeatTtl(ttl) => self.heartbeat_ttl = ttl,
+            SocketOption::HeartbeatTimeout(timeout) => self.heartbeat_timeout = timeout,
+            SocketOption::RoutingId(id) => self.routing_id = Some(id),
+            SocketOption::MaxMsgSize(size) => self.max_msg_size = size,
+            SocketOption::Mechanism(mechanism) => self.mechanism = mechanism,
+            SocketOption::InvertMatching(invert) => self.invert_matching = invert,
+            SocketOption::Conflate(conflate) => self.conflate = conflate,
+        }
+
+        self
+    }
+
+    /// `ZMQ_SNDHWM`
+    pub fn sndhwm(&self) -> usize {
+        self.sndhwm
+    }
+
+    /// `ZMQ_RCVHWM`
+    pub fn rcvhwm(&self) -> usize {
+        self.rcvhwm
+    }
+
+    /// `ZMQ_SNDTIMEO`
+    pub fn sndtimeo(&self) -> Option<Duration> {
+        self.sndtimeo
+    }
+
+    /// `ZMQ_RCVTIMEO`
+    pub fn rcvtimeo(&self) -> Option<Duration> {
+        self.rcvtimeo
+    }
+
+    /// `ZMQ_LINGER`
+    pub fn linger(&self) -> Option<Duration> {
+        self.linger
+    }
+
+    /// `ZMQ_RECONNECT_IVL` and friends.
+    pub fn reconnect(&self) -> &ReconnectPolicy {
+        &self.reconnect
+    }
+
+    /// `ZMQ_HEARTBEAT_IVL`
+    pub fn heartbeat_ivl(&self) -> Option<Duration> {
+        self.heartbeat_ivl
+    }
+
+    /// `ZMQ_HEARTBEAT_TTL`
+    pub fn heartbeat_ttl(&self) -> Option<Duration> {
+        self.heartbeat_ttl
+    }
+
+    /// `ZMQ_HEARTBEAT_TIMEOUT`, falling back on the heartbeat interval.
+    pub fn heartbeat_timeout(&self) -> Option<Duration> {
+        self.heartbeat_timeout.or(self.heartbeat_ivl)
+    }
+
+    /// `ZMQ_ROUTING_ID`
+    pub fn routing_id(&self) -> Option<&[u8]> {
+        self.routing_id.as_deref()
+    }
+
+    /// `ZMQ_MAXMSGSIZE`
+    pub fn max_msg_size(&self) -> Option<u64> {
+        self.max_msg_size
+    }
+
+    /// `ZMQ_MECHANISM`
+    pub fn mechanism(&self) -> Mechanism {
+        self.mechanism
+    }
+
+    /// `ZMQ_INVERT_MATCHING`
+    pub fn invert_matching(&self) -> bool {
+        self.invert_matching
+    }
+
+    /// `ZMQ_CONFLATE`
+    pub fn conflate(&self) -> bool {
+        self.conflate
+    }
+
+    /// Fail with `Unsupported` if an option is set that sockets don't act upon yet.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        let heartbeats = [
+            self.heartbeat_ivl,
+            self.heartbeat_ttl,
+            self.heartbeat_timeout,
+        ];
+
+        if heartbeats.iter().any(Option::is_some) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "heartbeats are not supported yet",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// -- SocketBuilder<S>
+
+/// A builder for sockets of type `S`, see [`socket`](crate::socket).
+#[derive(Debug)]
+pub struct SocketBuilder<S> {
+    options: SocketOptions,
+    _socket: PhantomData<fn() -> S>,
+}
+
+impl<S> SocketBuilder<S> {
+    pub(crate) fn new() -> Self {
+        Self {
+            options: SocketOptions::default(),
+            _socket: PhantomData,
+        }
+    }
+
+    /// Set a socket option.
+    pub fn option(mut self, option: SocketOption) -> Self {
+        self.options = self.options.with(option);
+        self
+    }
+
+    /// The options configured so far.
+    pub fn options(&self) -> &SocketOptions {
+        &self.options
+    }
+}
+
+impl<S> SocketBuilder<S>
+where
+    S: SocketType + Socket + From<Stream>,
+{
+    /// Connect the socket to an `endpoint` of the form `"tcp://127.0.0.1:5678"`.
+    pub fn connect(self, endpoint: &str) -> io::Result<S> {
+        let (transport, address) = split_endpoint(endpoint)?;
+        crate::connect_with(transport, address, self.options)
+    }
+
+    /// Bind the socket to an `endpoint` of the form `"tcp://127.0.0.1:5678"`.
+    ///
+    /// See [`bind`](crate::bind) for which sockets can be bound.
+    pub fn bind(self, endpoint: &str) -> io::Result<S> {
+        let (transport, address) = split_endpoint(endpoint)?;
+        crate::bind_with(transport, address, self.options)
+    }
+
+    /// Start the socket over an already established, user supplied, byte stream.
+    ///
+    /// See [`from_stream`](crate::from_stream) for details.
+    pub fn from_stream<C>(self, conn: C) -> io::Result<S>
+    where
+        C: Read + Write + Send + 'static,
+    {
+        let stream = Stream::custom(S::name(), Box::new(conn), None, self.options)?;
+        Ok(stream.into())
+    }
+
+    /// Start the socket over a user supplied byte stream, calling `reconnect` for new ones.
+    ///
+    /// See [`from_stream_with`](crate::from_stream_with) for details.
+    pub fn from_stream_with<C, F>(self, conn: C, mut reconnect: F) -> io::Result<S>
+    where
+        C: Read + Write + Send + 'static,
+        F: FnMut() -> io::Result<C> + Send + 'static,
+    {
+        let factory: Factory = Box::new(move || {
+            let conn: Box<dyn Connection> = Box::new(reconnect()?);
+            Ok(conn)
+        });
+
+        let stream = Stream::custom(S::name(), Box::new(conn), Some(factory), self.options)?;
+        Ok(stream.into())
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<S> SocketBuilder<S>
+where
+    S: SocketType,
+{
+    /// Perform the ZMTP handshake over `io` and produce a framed socket.
+    ///
+    /// ```rust,no_run
+    /// use zedmq::{framed::Pull, SocketOption};
+    ///
+    /// # async fn example<T>(io: T) -> std::io::Result<()>
+    /// # where T: futures::io::AsyncRead + futures::io::AsyncWrite + Unpin {
+    /// let socket: Pull<T> = zedmq::socket()
+    ///     .option(SocketOption::Conflate(true))
+    ///     .handshake(io)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn handshake<T>(self, io: T) -> io::Result<S>
+    where
+        S: crate::framed::FromFramed<T>,
+        T: futures::io::AsyncRead + futures::io::AsyncWrite + Unpin,
+    {
+        let inner = crate::framed::Framed::handshake(io, S::name(), self.options).await?;
+        Ok(S::from_framed(inner))
+    }
+}
+
+/// Split an endpoint such as `"tcp://127.0.0.1:5678"` into its transport and address.
+pub(crate) fn split_endpoint(endpoint: &str) -> io::Result<(&str, &str)> {
+    let mut parts = endpoint.splitn(2, "://");
+
+    match (parts.next(), parts.next()) {
+        (Some(transport), Some(address)) => Ok((transport, address)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is not of the form \"transport://address\"", endpoint),
+        )),
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    io,
+    time::Duration,
+};
+
+/// Why an attempt at (re)connecting failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Failure {
+    /// The peer could not be reached at all (refused or an unresolvable address.)
+    Refused,
+
+    /// The peer was reached but the ZMTP handshake with it failed.
+    Handshake,
+
+    /// Anything else, e.g. a timeout.
+    Other,
+}
+
+impl Failure {
+    /// Classify an error produced while opening a connection (before any handshake.)
+    pub(crate) fn of_connect(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::InvalidInput => Failure::Refused,
+            _ => Failure::Other,
+        }
+    }
+}
+
+/// How a socket goes about re-establishing a lost (or never made) connection.
+///
+/// The defaults mirror libzmq: retry every 100 ms (`ZMQ_RECONNECT_IVL`),
+/// forever, without any backoff (`ZMQ_RECONNECT_IVL_MAX` of zero.)
+///
+/// ```rust
+/// use std::time::Duration;
+/// use zedmq::ReconnectPolicy;
+///
+/// let policy = ReconnectPolicy::default()
+///     .interval(Duration::from_millis(50))
+///     .interval_max(Duration::from_secs(5))
+///     .jitter(Duration::from_millis(10))
+///     .max_attempts(20)
+///     .stop_on_refused(true);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    interval: Duration,
+    interval_max: Duration,
+    jitter: Duration,
+    max_attempts: Option<usize>,
+    stop_on_refused: bool,
+    stop_on_handshake_failure: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            interval_max: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+            max_attempts: None,
+            stop_on_refused: false,
+            stop_on_handshake_failure: false,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The initial period to wait between attempts (`ZMQ_RECONNECT_IVL`.)
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The cap of the exponential backoff (`ZMQ_RECONNECT_IVL_MAX`.)
+    ///
+    /// Every failed attempt doubles the wait until it reaches this maximum.
+    /// Anything not above `interval` (the default) disables backoff.
+    pub fn interval_max(mut self, interval_max: Duration) -> Self {
+        self.interval_max = interval_max;
+        self
+    }
+
+    /// Add a random wait of up to `jitter` on top of every interval.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Give up after `attempts` failed attempts in a row.
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Give up straight away if the peer refuses the connection or its address can't be resolved.
+    pub fn stop_on_refused(mut self, stop: bool) -> Self {
+        self.stop_on_refused = stop;
+        self
+    }
+
+    /// Give up straight away if the peer was reached but the ZMTP handshake failed.
+    pub fn stop_on_handshake_failure(mut self, stop: bool) -> Self {
+        self.stop_on_handshake_failure = stop;
+        self
+    }
+
+    /// Decide whether to keep going after `attempt` (counting from one) failed because of `failure`.
+    pub(crate) fn should_retry(&self, attempt: usize, failure: Failure) -> bool {
+        let exhausted = self.max_attempts.is_some_and(|max| attempt >= max);
+        let stop = match failure {
+            Failure::Refused => self.stop_on_refused,
+            Failure::Handshake => self.stop_on_handshake_failure,
+            Failure::Other => false,
+        };
+
+        !(exhausted || stop)
+    }
+
+    /// How long to wait after `attempt` (counting from one) failed.
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let mut delay = self.interval;
+
+        if self.interval_max > self.interval {
+            let shift = attempt.saturating_sub(1).min(31) as u32;
+            delay = self
+                .interval
+                .checked_mul(1 << shift)
+                .map_or(self.interval_max, |delay| delay.min(self.interval_max));
+        }
+
+        if self.jitter > Duration::from_millis(0) {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_usize(attempt);
+            let nanos = hasher.finish() % (self.jitter.as_nanos() as u64).max(1);
+            delay += Duration::from_nanos(nanos);
+        }
+
+        delay
+    }
+
+    /// Turn the error of the last attempt into the one reported back to the caller.
+    ///
+    /// It keeps the kind of the last attempt's error, and that error as its source.
+    pub(crate) fn give_up(&self, attempt: usize, err: io::Error) -> io::Error {
+        io::Error::new(
+            err.kind(),
+            GaveUp {
+                attempts: attempt,
+                last: err,
+            },
+        )
+    }
+}
+
+// -- GaveUp
+
+/// A reconnect policy gave up, after `attempts` attempts that ended with `last`.
+#[derive(Debug)]
+struct GaveUp {
+    attempts: usize,
+    last: io::Error,
+}
+
+impl fmt::Display for GaveUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gave up connecting after {} attempt(s): {}",
+            self.attempts, self.last
+        )
+    }
+}
+
+impl Error for GaveUp {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.last)
+    }
+}
@@ -1,56 +1,123 @@
-use std::{
-    cmp::max,
-    io::{self, Read, Write},
-};
+use std::io::{self, Read, Write};
 
-use crate::{
-    codec::{Frame, FrameBuf, FrameKind},
-    stream::Stream,
-};
+use crate::{codec::Command, stream::Stream, Message, MessageBuf};
 
+pub mod pub_t;
 pub mod pull_t;
 pub mod push_t;
 pub mod rep_t;
 pub mod req_t;
 pub mod sub_t;
-pub mod pub_t;
+pub(crate) mod trie;
+
+use self::{
+    pub_t::Pub,
+    pull_t::Pull,
+    push_t::Push,
+    rep_t::{Rep, RepPending},
+    req_t::{Req, ReqPending},
+    sub_t::Sub,
+};
 
-// -- LazyMessage
+/// Give socket types `from_stream` and `from_stream_with` constructors.
+macro_rules! impl_from_stream {
+    [$( $name:ident ),+] => {
+        $(
+            impl $name {
+                /// Start the socket over an already established, user supplied, byte stream.
+                ///
+                /// See [`from_stream`](crate::from_stream) for details.
+                pub fn from_stream<C>(conn: C) -> io::Result<Self>
+                where
+                    C: Read + Write + Send + 'static,
+                {
+                    crate::from_stream(conn)
+                }
 
-/// A lazy message will iterate over frames of a message until it hits a tail at which point it fuses.
-pub(crate) struct LazyMessage<'a> {
-    stream: &'a mut Stream,
-    witness: bool,
+                /// Start the socket over a user supplied byte stream, calling `reconnect` for new ones.
+                ///
+                /// See [`from_stream_with`](crate::from_stream_with) for details.
+                pub fn from_stream_with<C, F>(conn: C, reconnect: F) -> io::Result<Self>
+                where
+                    C: Read + Write + Send + 'static,
+                    F: FnMut() -> io::Result<C> + Send + 'static,
+                {
+                    crate::from_stream_with(conn, reconnect)
+                }
+            }
+        )+
+    };
 }
 
-impl<'a> From<&'a mut Stream> for LazyMessage<'a> {
-    fn from(stream: &'a mut Stream) -> Self {
-        Self {
-            stream,
-            witness: false,
-        }
-    }
+impl_from_stream![Pull, Push, Sub, Pub, Req, Rep];
+
+/// Give socket types an `options` getter for the options they were configured with.
+macro_rules! impl_options {
+    [$( $name:ident ),+] => {
+        $(
+            impl $name {
+                /// The options this socket was configured with.
+                pub fn options(&self) -> &$crate::SocketOptions {
+                    self.inner.options()
+                }
+            }
+        )+
+    };
+    [$( $name:ident<$t:ident> ),+] => {
+        $(
+            impl<$t> $name<$t> {
+                /// The options this socket was configured with.
+                pub fn options(&self) -> &$crate::SocketOptions {
+                    self.inner.options()
+                }
+            }
+        )+
+    };
 }
 
-impl Iterator for LazyMessage<'_> {
-    type Item = io::Result<FrameBuf>;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub(crate) use impl_options;
+
+impl_options![Pull, Push, Sub, Pub, Req, ReqPending, Rep, RepPending];
+
+/// Give socket types a `close` method that lingers on queued messages.
+macro_rules! impl_close {
+    [$( $name:ident ),+] => {
+        $(
+            impl $name {
+                /// Flush queued messages for up to the linger period and close the connection.
+                ///
+                /// Dropping the socket does the same, `close` just gets to report
+                /// errors, like the linger period expiring with messages left unsent.
+                pub fn close(mut self) -> io::Result<()> {
+                    self.inner.close()
+                }
+            }
+        )+
+    };
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.witness {
-            return None;
-        }
+impl_close![Pull, Push, Sub, Req, ReqPending, Rep, RepPending];
 
-        let frame = self.stream.recv_frame();
+// -- trait Handler
 
-        if let Ok(ref frame) = frame {
-            match frame.as_frame().kind()? {
-                FrameKind::MessageTail => self.witness = true,
-                FrameKind::MessagePart => (),
-                _ => return None,
-            }
-        }
+/// How a socket type reacts to the commands its peer sends in between messages.
+pub(crate) trait Handler {
+    /// Handle a SUBSCRIBE, CANCEL, JOIN or LEAVE from the peer.
+    fn command(&mut self, command: Command) -> io::Result<()>;
 
-        Some(frame)
+    /// Whether to take the message starting with `head`, the others are dropped uncopied.
+    #[inline]
+    fn accepts(&self, _head: &[u8]) -> bool {
+        true
+    }
+}
+
+/// For socket types that have no use for any of these commands.
+impl Handler for () {
+    #[inline]
+    fn command(&mut self, _: Command) -> io::Result<()> {
+        Ok(())
     }
 }
 
@@ -70,106 +137,36 @@ where
 
     /// Read bytes into some buffer.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.stream().ensure_connected().read(buf)
+        Read::read(self.stream(), buf)
     }
 
     /// Read bytes into some buffer.
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.stream().ensure_connected().write(buf)
+        Write::write(self.stream(), buf)
     }
 
+    /// Send a multi-part message.
     #[inline]
-    fn send<'a, I, S>(&mut self, mut data: I) -> io::Result<()>
+    fn send<M>(&mut self, message: M) -> io::Result<()>
     where
-        I: DoubleEndedIterator<Item = &'a S>,
-        S: AsRef<[u8]>,
-        S: 'a,
+        M: Into<MessageBuf>,
     {
-        let tail = data
-            .next_back()
-            .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?;
-
-        let body: Vec<_> = data.collect();
-
-        let capacity = max(
-            body.iter().map(|i| i.as_ref().len()).max().unwrap_or(0),
-            tail.as_ref().len(),
-        );
-
-        let mut frame = Vec::with_capacity(capacity);
-
-        for part in body {
-            let size = part.as_ref().len();
-
-            if size <= std::u8::MAX as usize {
-                // SHORT MESSAGE MORE
-                frame.push(0x01);
-                // SHORT SIZE
-                frame.push(size as u8);
-            } else {
-                // LONG MESSAGE MORE
-                frame.push(0x03);
-                // SHORT SIZE
-                frame.extend_from_slice(&(size as u32).to_be_bytes() as &[_]);
-            };
-
-            frame.extend_from_slice(part.as_ref());
-
-            self.write(&frame)?;
-
-            frame.clear();
-        }
-
-        frame.clear();
-
-        // SHORT MESSAGE LAST
-        frame.push(0x00);
-        // SHORT SIZE
-        frame.push(tail.as_ref().len() as u8);
-        frame.extend_from_slice(&tail.as_ref());
-
-        self.write(&frame)?;
-
-        Ok(())
+        self.stream().send(message.into().as_message())
     }
 
-    /// Read a frame and write it into the provided buffer slice.
-    fn recv_frame_into<'b>(&mut self, buf: &'b mut [u8]) -> io::Result<Frame<'b>> {
-        let n = self.read(buf)?;
-        let byte_slice = &buf[..n];
-        let frame_slice = Frame::new(byte_slice);
-
-        Ok(frame_slice)
+    /// Receive a multi-part message into `message`, reusing its storage.
+    ///
+    /// Once `message` has grown big enough receiving no longer allocates.
+    #[inline]
+    fn recv_into<'b>(&mut self, message: &'b mut MessageBuf) -> io::Result<Message<'b>> {
+        self.stream().recv_into(message, &mut ())
     }
 
-    // Receive a multi-part message as a 2d vec of bytes.
+    /// Receive a multi-part message.
     #[inline]
-    fn recv(&mut self) -> io::Result<Vec<Vec<u8>>> {
-        let mut frames = vec![];
-
-        loop {
-            let frame_buf = self.stream().recv_frame()?;
-            assert!(frame_buf.as_frame().kind().is_some());
-
-            if let Some(message) = frame_buf.as_frame().try_into_message() {
-                frames.push(message.body().to_vec());
-
-                if message.is_last() {
-                    break;
-                }
-            } else {
-                assert!(matches!(
-                    frame_buf.as_frame().kind(),
-                    Some(FrameKind::Command)
-                ));
-
-                panic!(
-                    "Unexpected command frame! {:#?}",
-                    frame_buf.as_frame().try_into_command()
-                );
-            }
-        }
-
-        Ok(frames)
+    fn recv(&mut self) -> io::Result<MessageBuf> {
+        let mut message = MessageBuf::new();
+        self.recv_into(&mut message)?;
+        Ok(message)
     }
 }
@@ -1,47 +1,323 @@
-use std::{cell::Cell, unimplemented};
-use std::io;
+use std::io::{self, IoSlice, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Instant;
 
-use crate::{prelude::Stream, stream::{Position, Transport}};
+use crate::{
+    codec::{Command, Encoder, Engine, Event, Message},
+    prelude::Stream,
+    stream::{linger, MAX_DRAIN},
+    MessageBuf, SocketOptions,
+};
 
-use super::Socket;
+use super::{trie::Trie, Handler, Socket};
 
+/// Subscriptions from ZMTP 3.1 peers come in as commands.
+impl Handler for Trie {
+    fn command(&mut self, command: Command) -> io::Result<()> {
+        match command {
+            Command::Subscribe(topic) => {
+                self.add(&topic);
+            }
 
+            Command::Cancel(topic) => {
+                self.remove(&topic);
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+// -- Subscriber
+
+/// A peer of a bound PUB socket, along with the topics it's subscribed to.
+///
+/// Nothing here ever blocks, a peer that doesn't keep up has messages
+/// dropped once its SNDHWM is reached.
+#[derive(Debug)]
+struct Subscriber {
+    conn: TcpStream,
+    engine: Engine,
+    topics: Trie,
+
+    /// Whether messages go out when they match none of the topics instead.
+    invert: bool,
+
+    /// Where the messages of ZMTP 3.0 peers, which are their subscriptions, are decoded into.
+    scratch: MessageBuf,
+}
+
+impl Subscriber {
+    fn new(conn: TcpStream, options: &SocketOptions) -> io::Result<Self> {
+        conn.set_nonblocking(true)?;
+        conn.set_nodelay(true)?;
+
+        Ok(Self {
+            conn,
+            engine: Engine::new("PUB", options),
+            topics: Trie::default(),
+            invert: options.invert_matching(),
+            scratch: MessageBuf::new(),
+        })
+    }
+
+    /// Take in what the peer sent so far, up to `MAX_DRAIN` bytes, and write out whatever it takes.
+    ///
+    /// A peer that keeps sending only has the rest taken in on the next
+    /// poll. Fails once the peer hung up or broke protocol, it should be
+    /// dropped then.
+    fn poll(&mut self) -> io::Result<()> {
+        let mut drained = 0;
+
+        while drained < MAX_DRAIN {
+            let read = self
+                .conn
+                .read(self.engine.read_buf(self.engine.read_size()));
+            self.engine.commit(*read.as_ref().unwrap_or(&0));
+
+            match read {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(n) => drained += n,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Err(err) = self.handle() {
+            // An ERROR may have been queued up to tell the peer why it's turned away.
+            let _ = self.flush();
+            return Err(err);
+        }
+
+        self.flush()
+    }
+
+    /// Act on the commands and messages taken in so far, its subscriptions.
+    fn handle(&mut self) -> io::Result<()> {
+        loop {
+            match self.engine.poll_into(&mut self.scratch)? {
+                Some(Event::Command(command)) => self.topics.command(command)?,
+
+                // ZMTP 3.0 peers send their subscriptions as messages.
+                Some(Event::Message) => match self.scratch.first().map(<[u8]>::split_first) {
+                    Some(Some((1, topic))) => {
+                        self.topics.add(topic);
+                    }
+
+                    Some(Some((0, topic))) => {
+                        self.topics.remove(topic);
+                    }
+
+                    _ => (),
+                },
+
+                Some(_) => (),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Send `message`, encoded as `frames`, if the peer is subscribed to it.
+    ///
+    /// The frames are written straight from where they are, only what the
+    /// peer doesn't take without blocking is copied and queued up. Behind
+    /// anything queued up already the message is queued up too, or dropped
+    /// past the SNDHWM.
+    fn send(&mut self, message: Message<'_>, frames: &[IoSlice<'_>]) -> io::Result<()> {
+        let head = message.first().unwrap_or_default();
+
+        if !self.engine.is_ready() || self.topics.matches(head) == self.invert {
+            return Ok(());
+        }
+
+        self.flush()?;
+
+        if !self.engine.output().is_empty() {
+            return self.engine.write_message(message);
+        }
+
+        let mut frames = frames.to_vec();
+        let mut remainder = &mut frames[..];
+
+        while !remainder.is_empty() {
+            match self.conn.write_vectored(remainder) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => IoSlice::advance_slices(&mut remainder, n),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        self.engine.write_remainder(remainder);
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.engine.output().is_empty() {
+            match self.conn.write(self.engine.output()) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => self.engine.consume_output(n),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Take on every peer waiting to be accepted by `listener`.
+fn accept(
+    listener: &TcpListener,
+    options: &SocketOptions,
+    subscribers: &mut Vec<Subscriber>,
+) -> io::Result<()> {
+    loop {
+        match listener.accept() {
+            Ok((conn, _)) => {
+                // A peer that's gone before it could be set up just isn't taken on.
+                if let Ok(subscriber) = Subscriber::new(conn, options) {
+                    subscribers.push(subscriber);
+                }
+            }
+
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// -- Pub
+
+/// A zmq PUB socket.
+#[derive(Debug)]
 pub struct Pub {
-    inner: Cell<Stream>,
+    pub(super) inner: Stream,
+
+    /// The peers of a bound socket, a connected one has none.
+    subscribers: Vec<Subscriber>,
+
+    /// Encodes messages once for all of the peers of a bound socket.
+    encoder: Encoder,
 }
 
 impl Pub {
-    pub fn send(&mut self, bytes: Vec<Vec<u8>>) -> io::Result<()> {
-        let stream = self.inner.get_mut();
-        let transport = stream.ensure_connected();
+    /// Send a message.
+    ///
+    /// A bound socket only sends it to the peers subscribed to it, or with
+    /// [`InvertMatching`](crate::SocketOption::InvertMatching) to the ones
+    /// that aren't. Peers that fall behind by more than the SNDHWM miss out
+    /// on it, this never blocks on any of them. The message is encoded once
+    /// and written to every peer from where it is.
+    pub fn send<M>(&mut self, message: M) -> io::Result<()>
+    where
+        M: Into<MessageBuf>,
+    {
+        let message = message.into();
 
         // depending on the transport used we may or may not have to perform
         // filtering locally.
-        match transport {
-            Transport::Tcp(Position::Connect(_)) => {
-                // we're on the connected end of a TCP session so rely on
-                // the client in order to perform filtering.
+        if self.inner.listener().is_none() {
+            // we're on the connected end of a session so rely on
+            // the client in order to perform filtering.
 
-                <Self as Socket>::send(self, bytes.iter())?;
-            },
+            return <Self as Socket>::send(self, message);
+        }
 
-            _ => unimplemented!(),
+        if message.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
         }
 
+        self.poll()?;
+
+        let frames = self.encoder.encode(message.as_message())?;
+
+        // Peers that hung up are dropped along the way.
+        self.subscribers
+            .retain_mut(|subscriber| subscriber.send(message.as_message(), &frames).is_ok());
+
+        Ok(())
+    }
+
+    /// Take on peers waiting to connect to a bound socket and take in the subscriptions of the rest.
+    ///
+    /// This never blocks and sends nothing. Sending does the same, a socket
+    /// that has nothing to send for a while can call this to not keep new
+    /// peers waiting on the handshake. Connected sockets have nothing to do.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let listener = match self.inner.listener() {
+            Some(listener) => listener,
+            None => return Ok(()),
+        };
+
+        accept(listener, self.inner.options(), &mut self.subscribers)?;
+
+        // Peers that hung up or broke protocol are dropped along the way.
+        self.subscribers
+            .retain_mut(|subscriber| subscriber.poll().is_ok());
+
         Ok(())
     }
+
+    /// The address the socket is bound to, or connected from.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Flush queued messages for up to the linger period and close the connection.
+    ///
+    /// A bound socket does so for every peer, all within the same linger
+    /// period. Dropping the socket does the same, `close` just gets to
+    /// report errors, like the linger period expiring with messages left
+    /// unsent.
+    pub fn close(mut self) -> io::Result<()> {
+        self.hang_up()?;
+        self.inner.close()
+    }
+
+    /// Flush what's queued up for every peer of a bound socket and hang up on them.
+    fn hang_up(&mut self) -> io::Result<()> {
+        let deadline = self
+            .inner
+            .options()
+            .linger()
+            .map(|linger| Instant::now() + linger);
+
+        self.subscribers
+            .drain(..)
+            .map(|mut subscriber| {
+                subscriber.conn.set_nonblocking(false)?;
+                linger(&mut subscriber.conn, &mut subscriber.engine, deadline)
+            })
+            .fold(Ok(()), io::Result::and)
+    }
+}
+
+impl Drop for Pub {
+    fn drop(&mut self) {
+        let _ = self.hang_up();
+    }
 }
 
 impl From<Stream> for Pub {
     fn from(inner: Stream) -> Self {
         Self {
-            inner: Cell::new(inner),
+            inner,
+            subscribers: vec![],
+            encoder: Encoder::default(),
         }
     }
 }
 
 impl Socket for Pub {
     fn stream(&mut self) -> &mut Stream {
-        self.inner.get_mut()
+        &mut self.inner
     }
 }
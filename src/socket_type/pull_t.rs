@@ -1,13 +1,16 @@
 use std::io::{self};
 
-use crate::stream::Stream;
+use crate::{
+    stream::{FrameReader, Stream},
+    Message, MessageBuf,
+};
 
 use super::Socket;
 
 /// A zmq PULL socket.
 #[derive(Debug)]
 pub struct Pull {
-    inner: Stream,
+    pub(super) inner: Stream,
 }
 
 impl From<Stream> for Pull {
@@ -18,9 +21,24 @@ impl From<Stream> for Pull {
 
 impl Pull {
     /// Receive a multi-part message.
-    pub fn recv(&mut self) -> io::Result<Vec<Vec<u8>>> {
+    pub fn recv(&mut self) -> io::Result<MessageBuf> {
         <Self as Socket>::recv(self)
     }
+
+    /// Receive a multi-part message into `message`, reusing its storage.
+    pub fn recv_into<'b>(&mut self, message: &'b mut MessageBuf) -> io::Result<Message<'b>> {
+        <Self as Socket>::recv_into(self, message)
+    }
+
+    /// Start receiving the next part of a message, reading its body as it comes in.
+    ///
+    /// This is meant for parts too large to be held in memory, nothing
+    /// but the frame header is read up front. Once the reader is done
+    /// with, and if [`FrameReader::more`] says so, the next part of the
+    /// same message is received by calling this again.
+    pub fn frame_reader(&mut self) -> io::Result<FrameReader<'_>> {
+        self.inner.frame_reader(&mut ())
+    }
 }
 
 impl Socket for Pull {
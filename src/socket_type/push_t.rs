@@ -1,13 +1,16 @@
 use std::io;
 
-use crate::stream::Stream;
+use crate::{
+    stream::{MessageWriter, Stream},
+    MessageBuf,
+};
 
 use super::Socket;
 
 /// A zmq PUSH socket.
 #[derive(Debug)]
 pub struct Push {
-    inner: Stream,
+    pub(super) inner: Stream,
 }
 
 impl From<Stream> for Push {
@@ -18,8 +21,32 @@ impl From<Stream> for Push {
 
 impl Push {
     /// Send a message.
-    pub fn send(&mut self, bytes: Vec<Vec<u8>>) -> io::Result<()> {
-        <Self as Socket>::send(self, bytes.iter())
+    pub fn send<M>(&mut self, message: M) -> io::Result<()>
+    where
+        M: Into<MessageBuf>,
+    {
+        <Self as Socket>::send(self, message)
+    }
+
+    /// Start sending a message whose parts are written, and read from wherever, one at a time.
+    ///
+    /// This is meant for parts too large to be held in memory:
+    ///
+    /// ```rust,no_run
+    /// # use zedmq::prelude::*;
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut socket: Push = zedmq::connect("tcp", "127.0.0.1:5678")?;
+    /// let file = std::fs::File::open("backup.tar")?;
+    /// let size = file.metadata()?.len();
+    ///
+    /// let mut writer = socket.message_writer()?;
+    /// writer.write_part(10, &b"backup.tar"[..], true)?;
+    /// writer.write_part(size, file, false)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn message_writer(&mut self) -> io::Result<MessageWriter<'_>> {
+        self.inner.message_writer()
     }
 }
 
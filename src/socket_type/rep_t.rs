@@ -1,6 +1,6 @@
 use std::io::{self};
 
-use crate::stream::Stream;
+use crate::{stream::Stream, MessageBuf};
 
 use super::Socket;
 
@@ -9,13 +9,16 @@ use super::Socket;
 /// A zmq REQ socket pending a response.
 #[derive(Debug)]
 pub struct RepPending {
-    inner: Stream,
+    pub(super) inner: Stream,
 }
 
 impl RepPending {
     /// Send a message.
-    pub fn send(mut self, bytes: Vec<Vec<u8>>) -> io::Result<Rep> {
-        <Self as Socket>::send(&mut self, bytes.iter())?;
+    pub fn send<M>(mut self, message: M) -> io::Result<Rep>
+    where
+        M: Into<MessageBuf>,
+    {
+        <Self as Socket>::send(&mut self, message)?;
         Ok(Rep { inner: self.inner })
     }
 }
@@ -31,7 +34,7 @@ impl Socket for RepPending {
 /// A zmq REP socket.
 #[derive(Debug)]
 pub struct Rep {
-    inner: Stream,
+    pub(super) inner: Stream,
 }
 
 impl From<Stream> for Rep {
@@ -42,7 +45,7 @@ impl From<Stream> for Rep {
 
 impl Rep {
     /// Recieve a multipart message with the pending REP socket.
-    pub fn recv(mut self) -> io::Result<(Vec<Vec<u8>>, RepPending)> {
+    pub fn recv(mut self) -> io::Result<(MessageBuf, RepPending)> {
         let data = <Self as Socket>::recv(&mut self)?;
         let Self { inner } = self;
         Ok((data, RepPending { inner }))
@@ -1,6 +1,6 @@
 use std::io;
 
-use crate::stream::Stream;
+use crate::{stream::Stream, MessageBuf};
 
 use super::Socket;
 
@@ -9,12 +9,12 @@ use super::Socket;
 /// A zmq REQ socket pending a response.
 #[derive(Debug)]
 pub struct ReqPending {
-    inner: Stream,
+    pub(super) inner: Stream,
 }
 
 impl ReqPending {
     /// recieve a message.
-    pub fn recv(mut self) -> io::Result<(Vec<Vec<u8>>, Req)> {
+    pub fn recv(mut self) -> io::Result<(MessageBuf, Req)> {
         let data = <Self as Socket>::recv(&mut self)?;
         let Self { inner } = self;
         Ok((data, Req { inner }))
@@ -38,13 +38,16 @@ impl From<Stream> for Req {
 /// A zmq REQ socket.
 #[derive(Debug)]
 pub struct Req {
-    inner: Stream,
+    pub(super) inner: Stream,
 }
 
 impl Req {
     /// Send a message.
-    pub fn send(mut self, bytes: Vec<Vec<u8>>) -> io::Result<ReqPending> {
-        <Self as Socket>::send(&mut self, bytes.iter())?;
+    pub fn send<M>(mut self, message: M) -> io::Result<ReqPending>
+    where
+        M: Into<MessageBuf>,
+    {
+        <Self as Socket>::send(&mut self, message)?;
         Ok(ReqPending { inner: self.inner })
     }
 }
@@ -1,171 +1,193 @@
-use std::hash::Hasher;
-use std::io::{self, Write};
-use std::{cell::Cell, collections::hash_map::DefaultHasher};
-use std::{convert::TryInto, hash::Hash};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use super::{trie::Trie, Handler, Socket};
+use crate::{
+    codec::{Command, Engine},
+    stream::Stream,
+    Message, MessageBuf,
+};
+
+/// Lock the topics of a SUB socket, a panic elsewhere doesn't leave them in a broken state.
+pub(crate) fn lock(topics: &Mutex<Trie>) -> MutexGuard<'_, Trie> {
+    topics.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-use super::{LazyMessage, Socket};
-use crate::{codec::FrameBuf, stream::Stream};
+/// Queue up a subscription to every one of `topics` on a fresh connection.
+pub(crate) fn replay(topics: &Trie, engine: &mut Engine) -> io::Result<()> {
+    topics.try_for_each(|topic| engine.write_subscription(topic, true))
+}
 
-#[derive(Clone, Debug)]
-enum SubscriptionTopic {
-    /// An empty topic (matches everything.)
-    Empty,
+// -- Filters
 
-    /// A literal topic is any topic 8 bytes or smaller.
-    ///
-    /// We store the literal to avoid extrenious hashing of small prefixes.
-    ///
-    Literal([u8; 8]),
+/// A predicate over the first frame of a message.
+type Predicate = Box<dyn Fn(&[u8]) -> bool + Send + Sync>;
 
-    /// A hashed topic is the hash of any topic larger than 8 bytes.
-    ///
-    /// It matches if the hash of the first `length` bytes slice matches
-    /// `value`.
-    ///
-    Hashed { value: u64, length: u8 },
+/// What a SUB socket holds messages up against besides its subscriptions.
+#[derive(Default)]
+pub(crate) struct Filters {
+    predicates: Vec<Predicate>,
+
+    /// Whether the messages taken are the ones matching none of the subscriptions.
+    invert: bool,
 }
 
-impl From<Stream> for Sub {
-    fn from(inner: Stream) -> Self {
+impl Filters {
+    pub(crate) fn new(invert: bool) -> Self {
         Self {
-            inner: Cell::new(inner),
-            topics: vec![],
+            predicates: vec![],
+            invert,
         }
     }
+
+    pub(crate) fn push<F>(&mut self, predicate: F)
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.predicates.push(Box::new(predicate));
+    }
+
+    /// Whether to take a message starting with `head`, given whether it's `subscribed` to.
+    pub(crate) fn accepts(&self, subscribed: bool, head: &[u8]) -> bool {
+        subscribed != self.invert && self.predicates.iter().all(|predicate| predicate(head))
+    }
 }
 
-/// A ZMQ SUB socket.
-pub struct Sub {
-    inner: Cell<Stream>,
-    topics: Vec<SubscriptionTopic>,
+impl fmt::Debug for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filters")
+            .field("predicates", &self.predicates.len())
+            .field("invert", &self.invert)
+            .finish()
+    }
 }
 
-impl Sub {
-    /// Subscribe to a topic.
-    pub fn subscribe(&mut self, topic: &[u8]) -> io::Result<()> {
-        // Note down the subscribing topic locally for prefix matching
-        // when receiving (its a new block because I wanted to reuse "topic" as a name.)
-        {
-            let slim_topic: Result<[u8; 8], _> = topic.try_into();
-            let topic_entry = match (topic.len(), slim_topic) {
-                (0, _) => SubscriptionTopic::Empty,
-                (_, Ok(slim)) => SubscriptionTopic::Literal(slim),
-                (length, _) => {
-                    let mut s = DefaultHasher::new();
-                    topic.hash(&mut s);
-                    let value = s.finish();
-                    let length = length
-                        .try_into()
-                        .expect("Subscription topics can only take 255 bytes maximum");
-                    SubscriptionTopic::Hashed { value, length }
-                }
-            };
-
-            self.topics.push(topic_entry);
-        }
+// -- Matching
 
-        let subscribe = if false {
-            // The below code is acceptable for ZMTP 3.1 but not for 3.0 (which is what we are by default.)
+/// Lets through the messages a SUB socket takes, the others are dropped before being copied.
+pub(crate) struct Matching<'a> {
+    /// Whether a message starting with the given bytes matches a subscription.
+    pub(crate) subscribed: &'a (dyn Fn(&[u8]) -> bool + Sync),
+    pub(crate) filters: &'a Filters,
+}
 
-            let mut subscribe = vec![
-                0x4, // SHORT COMMAND
-                0x0, // LENGTH OF FRAME
-                // subscribe tag `0xd0 | "SUBSCRIBE".len()`
-                // don't ask me why there's a 0xd0 in there
-                0xd9,
-            ];
+impl Handler for Matching<'_> {
+    #[inline]
+    fn command(&mut self, _: Command) -> io::Result<()> {
+        Ok(())
+    }
 
-            subscribe.extend_from_slice("SUBSCRIBE".as_bytes());
-            subscribe.extend_from_slice(topic);
-            subscribe[1] = subscribe.len() as u8;
+    #[inline]
+    fn accepts(&self, head: &[u8]) -> bool {
+        self.filters.accepts((self.subscribed)(head), head)
+    }
+}
 
-            subscribe
-        } else {
-            let mut subscribe = vec![0x00, 0xFF, 0x1];
+// -- Sub
 
-            subscribe.extend_from_slice(&topic);
-            subscribe[1] = 1 + topic.len() as u8;
-            subscribe
-        };
+impl From<Stream> for Sub {
+    fn from(mut inner: Stream) -> Self {
+        // Every fresh connection is told about the subscriptions so far.
+        let topics = Arc::<Mutex<Trie>>::default();
+        let replayed = topics.clone();
 
-        self.inner
-            .get_mut()
-            .ensure_connected()
-            .write(&subscribe)
-            .map(|_| ())
-    }
+        inner.on_connect(move |engine| replay(&lock(&replayed), engine));
 
-    /// Recieve a message that matches a subscribed topic prefix.
-    #[inline]
-    pub fn recv(&mut self) -> io::Result<Vec<Vec<u8>>> {
-        fn topic_prefix_match(expected: &SubscriptionTopic, bytes: &[u8]) -> bool {
-            match expected {
-                SubscriptionTopic::Empty => true,
-                SubscriptionTopic::Literal(sl) => bytes.starts_with(sl),
-                SubscriptionTopic::Hashed { value, length } => {
-                    let mut s = DefaultHasher::new();
-                    let tail = &bytes[..(*length as usize)];
-                    tail.hash(&mut s);
-                    s.finish() == *value
-                }
-            }
+        let filters = Filters::new(inner.options().invert_matching());
+
+        Self {
+            inner,
+            topics,
+            filters,
         }
+    }
+}
 
-        let stream = self.inner.get_mut();
+/// A ZMQ SUB socket.
+#[derive(Debug)]
+pub struct Sub {
+    pub(super) inner: Stream,
+    topics: Arc<Mutex<Trie>>,
+    filters: Filters,
+}
 
-        loop {
-            let message = LazyMessage {
-                stream,
-                witness: false,
-            };
+impl Sub {
+    /// Subscribe to a topic.
+    ///
+    /// Subscribing to a topic more than once only tells the publisher the first time, and takes as
+    /// many `unsubscribe`s to undo.
+    pub fn subscribe(&mut self, topic: &[u8]) -> io::Result<()> {
+        // A fresh connection is told about every topic as it's made, connect
+        // first so that the topic isn't told about twice.
+        self.inner.ensure_connected()?;
 
-            let mut stream = message.fuse();
+        // Note down the subscribing topic locally for prefix matching when receiving.
+        let added = lock(&self.topics).add(topic);
 
-            let raw_frame = stream
-                .next()
-                .ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))??;
+        match added {
+            true => self.inner.write_subscription(topic, true),
+            false => Ok(()),
+        }
+    }
 
-            let frame = match raw_frame.as_frame().try_into_message() {
-                Some(frame) => frame,
-                None => continue,
-            };
+    /// Unsubscribe from a topic, undoing one `subscribe` to it.
+    ///
+    /// Topics that were never subscribed to are ignored.
+    pub fn unsubscribe(&mut self, topic: &[u8]) -> io::Result<()> {
+        self.inner.ensure_connected()?;
 
-            let prefix_match = |topic| topic_prefix_match(topic, &frame.body());
+        let forgotten = lock(&self.topics).remove(topic);
 
-            if self.topics.iter().any(prefix_match) {
-                let mut message = vec![];
+        match forgotten {
+            true => self.inner.write_subscription(topic, false),
+            false => Ok(()),
+        }
+    }
 
-                'inner: loop {
-                    let raw_frame = stream
-                        .next()
-                        .ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))??;
+    /// Only take messages whose first frame satisfies `predicate`, on top of matching a subscription.
+    ///
+    /// Every predicate has to be satisfied. Unlike subscriptions the
+    /// publisher knows nothing of these, messages are still sent our way and
+    /// dropped on arrival.
+    pub fn filter<F>(&mut self, predicate: F)
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.filters.push(predicate);
+    }
 
-                    let part = match raw_frame.as_frame().try_into_message() {
-                        Some(frame) => frame,
-                        None => continue 'inner,  // ignore commands while constructing a multipart message.
-                    };
+    /// Recieve a message that matches a subscribed topic prefix and the filters.
+    #[inline]
+    pub fn recv(&mut self) -> io::Result<MessageBuf> {
+        let mut message = MessageBuf::new();
+        self.recv_into(&mut message)?;
+        Ok(message)
+    }
 
-                    message.push(part.body().to_owned());
+    /// Recieve a matching message into `message`, reusing its storage.
+    ///
+    /// Messages that don't are dropped without being copied into `message`.
+    pub fn recv_into<'b>(&mut self, message: &'b mut MessageBuf) -> io::Result<Message<'b>> {
+        let topics = &self.topics;
 
-                    if part.is_last() {
-                        break;
-                    }
-                };
+        let mut matching = Matching {
+            subscribed: &|head| lock(topics).matches(head),
+            filters: &self.filters,
+        };
 
-                return Ok(message);
-            }
-        }
+        self.inner.recv_into(message, &mut matching)
     }
 
     /// Receive a multipart message without performing prefix checks.
     #[inline]
-    pub fn recv_unchecked(&mut self) -> io::Result<Vec<Vec<u8>>> {
+    pub fn recv_unchecked(&mut self) -> io::Result<MessageBuf> {
         <Self as Socket>::recv(self)
     }
 }
 
 impl Socket for Sub {
     fn stream(&mut self) -> &mut crate::stream::Stream {
-        self.inner.get_mut()
+        &mut self.inner
     }
 }
@@ -0,0 +1,404 @@
+use std::io;
+
+// -- Node
+
+/// A node of the trie, along with how many subscriptions end right here.
+#[derive(Debug, Default)]
+struct Node {
+    count: usize,
+
+    /// Ordered by the first byte of their label, no two share it.
+    children: Vec<Edge>,
+}
+
+/// The path from a node to one of its children.
+///
+/// Chains of nodes that nothing ends at and that don't branch are folded
+/// into a single edge, so the trie only gets as deep as the topics diverge.
+#[derive(Debug)]
+struct Edge {
+    label: Vec<u8>,
+    node: Node,
+}
+
+impl Node {
+    /// The index of the child whose label starts with `first`.
+    #[inline]
+    fn child(&self, first: u8) -> Result<usize, usize> {
+        self.children
+            .binary_search_by_key(&first, |edge| edge.label[0])
+    }
+
+    /// The node at the end of `path`, a series of child indices.
+    fn at(&mut self, path: &[usize]) -> &mut Node {
+        path.iter()
+            .fold(self, |node, &index| &mut node.children[index].node)
+    }
+
+    /// Restore the shape of the trie below the child at `index` after a subscription went away.
+    fn prune(&mut self, index: usize) {
+        let edge = &mut self.children[index];
+
+        if edge.node.count > 0 {
+            return;
+        }
+
+        match edge.node.children.len() {
+            0 => {
+                self.children.remove(index);
+            }
+
+            1 => {
+                if let Some(only) = edge.node.children.pop() {
+                    edge.label.extend_from_slice(&only.label);
+                    edge.node = only.node;
+                }
+            }
+
+            _ => (),
+        }
+    }
+}
+
+// -- Trie
+
+/// Topic prefixes, matched exactly against the first frame of a message.
+///
+/// Every topic is counted so subscribing to one twice takes two removals
+/// before it's gone. Matching only takes as long as the first frame is, no
+/// matter how many topics there are.
+#[derive(Debug, Default)]
+pub(crate) struct Trie {
+    root: Node,
+}
+
+impl Trie {
+    /// Add `topic`, returning whether it's new.
+    pub(crate) fn add(&mut self, topic: &[u8]) -> bool {
+        let mut node = &mut self.root;
+        let mut rest = topic;
+
+        while let Some(&first) = rest.first() {
+            let index = match node.child(first) {
+                Ok(index) => index,
+                Err(index) => {
+                    let leaf = Edge {
+                        label: rest.to_vec(),
+                        node: Node::default(),
+                    };
+
+                    node.children.insert(index, leaf);
+                    rest = &[];
+                    node = &mut node.children[index].node;
+                    break;
+                }
+            };
+
+            let edge = &mut node.children[index];
+            let common = edge
+                .label
+                .iter()
+                .zip(rest)
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            // The topic ends or diverges half way along the edge, split it there.
+            if common < edge.label.len() {
+                let tail = edge.label.split_off(common);
+                let below = std::mem::take(&mut edge.node);
+
+                edge.node.children.push(Edge {
+                    label: tail,
+                    node: below,
+                });
+            }
+
+            rest = &rest[common..];
+            node = &mut node.children[index].node;
+        }
+
+        debug_assert!(rest.is_empty());
+
+        node.count += 1;
+        node.count == 1
+    }
+
+    /// Remove `topic` once, returning whether that was the last of it.
+    ///
+    /// Topics that were never added are ignored.
+    pub(crate) fn remove(&mut self, topic: &[u8]) -> bool {
+        let path = match self.path(topic) {
+            Some(path) => path,
+            None => return false,
+        };
+
+        let node = self.root.at(&path);
+
+        if node.count == 0 {
+            return false;
+        }
+
+        node.count -= 1;
+
+        if node.count > 0 {
+            return false;
+        }
+
+        // The node may go away, which may in turn leave its parent with nothing to branch on.
+        if let Some((&index, above)) = path.split_last() {
+            self.root.at(above).prune(index);
+
+            if let Some((&index, above)) = above.split_last() {
+                self.root.at(above).prune(index);
+            }
+        }
+
+        true
+    }
+
+    /// Whether any topic is a prefix of `head`.
+    pub(crate) fn matches(&self, head: &[u8]) -> bool {
+        let mut node = &self.root;
+        let mut rest = head;
+
+        loop {
+            if node.count > 0 {
+                return true;
+            }
+
+            let edge = match rest.first().map(|&first| node.child(first)) {
+                Some(Ok(index)) => &node.children[index],
+                _ => return false,
+            };
+
+            if !rest.starts_with(&edge.label) {
+                return false;
+            }
+
+            rest = &rest[edge.label.len()..];
+            node = &edge.node;
+        }
+    }
+
+    /// Call `f` with every distinct topic, stopping at the first error.
+    pub(crate) fn try_for_each<F>(&self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let mut topic = vec![];
+        let mut pending = vec![(0, &[][..], &self.root)];
+
+        while let Some((depth, label, node)) = pending.pop() {
+            topic.truncate(depth);
+            topic.extend_from_slice(label);
+
+            if node.count > 0 {
+                f(&topic)?;
+            }
+
+            let depth = topic.len();
+
+            pending.extend(
+                node.children
+                    .iter()
+                    .map(|edge| (depth, &edge.label[..], &edge.node)),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The child indices leading to the node `topic` ends at, if there is one.
+    fn path(&self, topic: &[u8]) -> Option<Vec<usize>> {
+        let mut path = vec![];
+        let mut node = &self.root;
+        let mut rest = topic;
+
+        while let Some(&first) = rest.first() {
+            let index = node.child(first).ok()?;
+            let edge = &node.children[index];
+
+            rest = rest.strip_prefix(&edge.label[..])?;
+            node = &edge.node;
+            path.push(index);
+        }
+
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every distinct topic, in order.
+    fn topics(trie: &Trie) -> Vec<Vec<u8>> {
+        let mut topics = vec![];
+
+        trie.try_for_each(|topic| {
+            topics.push(topic.to_vec());
+            Ok(())
+        })
+        .unwrap();
+
+        topics.sort();
+        topics
+    }
+
+    /// How many edges there are, checking the trie has the shape it's meant to along the way.
+    fn edges(node: &Node) -> usize {
+        assert!(node
+            .children
+            .windows(2)
+            .all(|pair| pair[0].label[0] < pair[1].label[0]));
+
+        node.children
+            .iter()
+            .map(|edge| {
+                assert!(!edge.label.is_empty());
+
+                // Nothing ends at it and it doesn't branch, it should have been folded away.
+                assert!(edge.node.count > 0 || edge.node.children.len() > 1);
+
+                1 + edges(&edge.node)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn topics_are_counted() {
+        let mut trie = Trie::default();
+
+        assert!(trie.add(b"abc"));
+        assert!(!trie.add(b"abc"));
+        assert!(trie.add(b"ab"));
+
+        assert!(!trie.remove(b"abc"));
+        assert!(trie.matches(b"abcd"));
+        assert!(trie.remove(b"abc"));
+
+        assert!(trie.matches(b"abd"));
+        assert!(trie.remove(b"ab"));
+        assert!(!trie.matches(b"abc"));
+        assert_eq!(edges(&trie.root), 0);
+    }
+
+    #[test]
+    fn edges_split_and_merge_back() {
+        let mut trie = Trie::default();
+
+        trie.add(b"abcd");
+        trie.add(b"abxy");
+        trie.add(b"ab");
+        trie.add(b"b");
+
+        assert_eq!(edges(&trie.root), 4);
+        assert_eq!(topics(&trie), vec![&b"ab"[..], b"abcd", b"abxy", b"b"]);
+
+        // "ab" is left with a single child to fold into.
+        trie.remove(b"abxy");
+        trie.remove(b"ab");
+
+        assert_eq!(edges(&trie.root), 2);
+        assert_eq!(trie.root.children[0].label, b"abcd");
+
+        assert!(trie.matches(b"abcde"));
+        assert!(!trie.matches(b"ab"));
+        assert!(!trie.matches(b"abx"));
+
+        // Splitting what was merged still works.
+        trie.add(b"abc");
+
+        assert_eq!(edges(&trie.root), 3);
+        assert_eq!(topics(&trie), vec![&b"abc"[..], b"abcd", b"b"]);
+    }
+
+    #[test]
+    fn the_empty_topic_matches_everything() {
+        let mut trie = Trie::default();
+
+        assert!(!trie.matches(b""));
+
+        assert!(trie.add(b""));
+        trie.add(b"a");
+
+        assert!(trie.matches(b""));
+        assert!(trie.matches(b"anything"));
+        assert_eq!(topics(&trie), vec![&b""[..], b"a"]);
+
+        assert!(trie.remove(b""));
+        assert!(!trie.matches(b"b"));
+        assert!(trie.matches(b"a"));
+    }
+
+    #[test]
+    fn removing_what_was_never_added_changes_nothing() {
+        let mut trie = Trie::default();
+
+        assert!(!trie.remove(b""));
+        assert!(!trie.remove(b"a"));
+
+        trie.add(b"abc");
+        trie.add(b"abd");
+
+        // Neither the node the two branch at nor a part way along an edge is a topic.
+        assert!(!trie.remove(b"ab"));
+        assert!(!trie.remove(b"a"));
+        assert!(!trie.remove(b"abcd"));
+        assert!(!trie.remove(b""));
+
+        // Nor is a topic once it's been removed as many times as it was added.
+        assert!(trie.remove(b"abc"));
+        assert!(!trie.remove(b"abc"));
+        assert!(!trie.matches(b"abc"));
+
+        assert!(trie.matches(b"abd"));
+        assert_eq!(edges(&trie.root), 1);
+    }
+
+    #[test]
+    fn hundreds_of_thousands_of_topics() {
+        const TOPICS: u32 = 300_000;
+
+        let topic = |n: u32| format!("sensors/{}/{}", n % 1000, n).into_bytes();
+        let mut trie = Trie::default();
+
+        for n in 0..TOPICS {
+            assert!(trie.add(&topic(n)));
+        }
+
+        for n in 0..TOPICS {
+            assert!(trie.matches(&topic(n)));
+        }
+
+        assert!(!trie.matches(b"sensors/1/2"));
+        assert!(!trie.matches(b"sensors/"));
+
+        let mut counted = 0;
+
+        trie.try_for_each(|_| {
+            counted += 1;
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(counted, TOPICS);
+
+        // Removing every other topic leaves the rest matching, and the trie in shape.
+        for n in (0..TOPICS).step_by(2) {
+            assert!(trie.remove(&topic(n)));
+        }
+
+        for n in 0..TOPICS {
+            assert_eq!(trie.matches(&topic(n)), n % 2 == 1);
+        }
+
+        edges(&trie.root);
+
+        for n in (1..TOPICS).step_by(2) {
+            assert!(trie.remove(&topic(n)));
+        }
+
+        assert!(trie.root.children.is_empty());
+    }
+}
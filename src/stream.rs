@@ -1,34 +1,65 @@
-use std::io::{self, Read, Write};
-use std::net::TcpListener;
-use std::net::TcpStream;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, IoSlice, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::codec::{FrameBuf, ZMTP};
+use crate::{
+    codec::{
+        encode_header, Encoder, Engine, Event, FrameKind, Message, MessageBuf, Metadata, READ_CHUNK,
+    },
+    reconnect::Failure,
+    socket_type::Handler,
+    SocketOptions,
+};
+
+/// The most that's read in one go when draining the peer to only take its latest message.
+pub(crate) const MAX_DRAIN: usize = 16 * READ_CHUNK;
+
+/// The longest hanging up waits on the peer to hang up too, once what was queued up went out.
+pub(crate) const HANG_UP_WAIT: Duration = Duration::from_millis(100);
 
 // -- Transport
 
-#[derive(Debug)]
-pub(crate) enum Position<L, R> {
-    Connect(L),
-    Bind(R),
-}
+/// Any user supplied byte stream that ZMTP can be spoken over.
+pub(crate) trait Connection: Read + Write + Send {}
 
-#[derive(Debug)]
+impl<T> Connection for T where T: Read + Write + Send {}
+
+/// A closure producing fresh user supplied connections when reconnecting.
+pub(crate) type Factory = Box<dyn FnMut() -> io::Result<Box<dyn Connection>> + Send>;
+
+/// A connection to a single peer.
+///
+/// Bound sockets listen through their `Endpoint` instead and keep track of
+/// the peers they accept themselves, so they never have one of these.
 pub(crate) enum Transport {
-    Tcp(Position<TcpStream, TcpListener>),
+    Tcp(TcpStream),
+    Custom(Box<dyn Connection>),
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(stream) => f.debug_tuple("Tcp").field(stream).finish(),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
 }
 
 impl Write for Transport {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match self {
-            Self::Tcp(Position::Connect(stream)) => stream.write(buf),
-            Self::Tcp(Position::Bind(_)) => unimplemented!(),
+            Self::Tcp(stream) => stream.write(buf),
+            Self::Custom(conn) => conn.write(buf),
         }
     }
 
     fn flush(&mut self) -> io::Result<()> {
         match self {
-            Self::Tcp(Position::Connect(stream)) => stream.flush(),
-            Self::Tcp(Position::Bind(_)) => Ok(()),
+            Self::Tcp(stream) => stream.flush(),
+            Self::Custom(conn) => conn.flush(),
         }
     }
 }
@@ -36,113 +67,951 @@ impl Write for Transport {
 impl Read for Transport {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
-            Self::Tcp(Position::Connect(stream)) => stream.read(buf),
-            Self::Tcp(Position::Bind(_)) => unimplemented!(),
+            Self::Tcp(stream) => stream.read(buf),
+            Self::Custom(conn) => conn.read(buf),
+        }
+    }
+}
+
+// -- Endpoint
+
+/// Where (and how) a `Stream` gets its connections from.
+pub(crate) enum Endpoint {
+    /// A TCP address to connect to.
+    Tcp(String),
+
+    /// A user supplied connection, optionally with a way to produce new ones.
+    Custom(Option<Factory>),
+
+    /// A listener for peers to connect to, there's no connecting anywhere.
+    Bound(TcpListener),
+}
+
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => f.debug_tuple("Tcp").field(address).finish(),
+            Self::Custom(factory) => f
+                .debug_tuple("Custom")
+                .field(&factory.as_ref().map(|_| ".."))
+                .finish(),
+            Self::Bound(listener) => f.debug_tuple("Bound").field(listener).finish(),
+        }
+    }
+}
+
+// -- handshake
+
+/// Drive `engine` through the ZMTP handshake over the blocking `io`.
+pub(crate) fn handshake<T>(io: &mut T, engine: &mut Engine) -> io::Result<()>
+where
+    T: Read + Write,
+{
+    loop {
+        io.write_all(engine.output())?;
+        engine.consume_output(engine.output().len());
+
+        if engine.is_ready() {
+            return Ok(());
+        }
+
+        let event = match engine.poll() {
+            Ok(event) => event,
+            Err(err) => {
+                // An ERROR may have been queued up to tell the peer why it's turned away.
+                let _ = io.write_all(engine.output());
+                return Err(err);
+            }
+        };
+
+        // Only wait on the peer once everything it needs from us has been sent.
+        if event.is_none() && engine.output().is_empty() {
+            match io.read(engine.read_buf(engine.read_size())) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(n) => engine.commit(n),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+// -- linger
+
+/// Flush what `engine` has queued up over the blocking `stream` until `deadline` and hang up.
+///
+/// `None` flushes for as long as it takes. Our side is then shut down,
+/// if anything was queued up we also wait a little (no longer than
+/// `HANG_UP_WAIT` or the deadline) for the peer to hang up, so that the
+/// tail of it isn't reset along with input we never read.
+pub(crate) fn linger(
+    stream: &mut TcpStream,
+    engine: &mut Engine,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    // How long until the deadline, `Ok(None)` is forever.
+    let remaining = || match deadline {
+        None => Ok(None),
+        Some(deadline) => match deadline.saturating_duration_since(Instant::now()) {
+            left if left.is_zero() => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the linger period expired with messages still queued",
+            )),
+            left => Ok(Some(left)),
+        },
+    };
+
+    let queued = !engine.output().is_empty();
+
+    let flushed = (|| {
+        while !engine.output().is_empty() {
+            stream.set_write_timeout(remaining()?)?;
+
+            match stream.write(engine.output()) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => engine.consume_output(n),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    })();
+
+    // Hang up our side and let the peer read everything up to it
+    // before it hangs up on us. Closing with unread input would
+    // reset the connection instead.
+    if stream.shutdown(Shutdown::Write).is_ok() && queued {
+        let wait = Instant::now() + HANG_UP_WAIT;
+        let until = deadline.map_or(wait, |deadline| deadline.min(wait));
+        let mut scratch = [0u8; 512];
+
+        loop {
+            let left = until.saturating_duration_since(Instant::now());
+
+            if left.is_zero() || stream.set_read_timeout(Some(left)).is_err() {
+                break;
+            }
+
+            match stream.read(&mut scratch) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => break,
+            }
         }
     }
+
+    flushed
+}
+
+// -- ConnectHook
+
+/// Called with the engine of every fresh connection once the handshake is done.
+///
+/// Socket types replay their per-connection state with it, like the
+/// subscriptions of a SUB socket, it is queued up ahead of anything else.
+pub(crate) struct ConnectHook(Box<OnConnect>);
+
+type OnConnect = dyn FnMut(&mut Engine) -> io::Result<()> + Send;
+
+impl ConnectHook {
+    pub(crate) fn new<F>(hook: F) -> Self
+    where
+        F: FnMut(&mut Engine) -> io::Result<()> + Send + 'static,
+    {
+        Self(Box::new(hook))
+    }
+
+    #[inline]
+    pub(crate) fn call(&mut self, engine: &mut Engine) -> io::Result<()> {
+        (self.0)(engine)
+    }
+}
+
+impl fmt::Debug for ConnectHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ConnectHook(..)")
+    }
 }
 
 // -- Stream<'a>
 
 /// The stream struct represents the underlying connection primitive.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Stream {
     socket_type: &'static str,
-    address: String,
+    endpoint: Endpoint,
+    options: SocketOptions,
     transport: Option<Transport>,
+    engine: Option<Engine>,
+    encoder: Encoder,
+    on_connect: Option<ConnectHook>,
+
+    /// Whether the TCP transport is currently in non-blocking mode.
+    nonblocking: bool,
 }
 
 impl Stream {
     /// Given an `address` produce a `Stream` that is connected even if connecting may block.
-    pub(super) fn connected(socket_type: &'static str, address: &str) -> Self {
+    pub(super) fn connected(
+        socket_type: &'static str,
+        address: &str,
+        options: SocketOptions,
+    ) -> io::Result<Self> {
+        options.check()?;
+
         let mut stream = Self {
             socket_type,
-            address: address.to_string(),
+            endpoint: Endpoint::Tcp(address.to_string()),
+            options,
             transport: None,
+            engine: None,
+            encoder: Encoder::default(),
+            on_connect: None,
+            nonblocking: false,
         };
 
-        stream.ensure_connected();
+        stream.ensure_connected()?;
 
-        stream
+        Ok(stream)
     }
 
-    pub(super) fn connect(&self) -> io::Result<Transport> {
-        let address = self.address.clone();
-        let produce = move || {
-            Ok(Transport::Tcp(Position::Connect(TcpStream::connect(
-                address,
-            )?)))
-        };
+    /// Produce a `Stream` over a user supplied connection, performing the handshake on it.
+    ///
+    /// If a `factory` is provided it is used to produce new connections
+    /// whenever the current one goes away.
+    pub(super) fn custom(
+        socket_type: &'static str,
+        conn: Box<dyn Connection>,
+        factory: Option<Factory>,
+        options: SocketOptions,
+    ) -> io::Result<Self> {
+        options.check()?;
+
+        let mut transport = Transport::Custom(conn);
+        let mut engine = Engine::new(socket_type, &options);
 
-        let transport = ZMTP::connect(produce)?
-            .greet(crate::ZMQ_VERSION, false)?
-            .ready(self.socket_type)?;
+        handshake(&mut transport, &mut engine)?;
 
-        Ok(transport)
+        Ok(Self {
+            socket_type,
+            endpoint: Endpoint::Custom(factory),
+            options,
+            transport: Some(transport),
+            engine: Some(engine),
+            encoder: Encoder::default(),
+            on_connect: None,
+            nonblocking: false,
+        })
+    }
+
+    /// Given an `address` produce a `Stream` that listens on it for peers.
+    ///
+    /// The listener never blocks, it's up to the socket type to accept
+    /// peers and to keep track of them.
+    pub(super) fn bound(
+        socket_type: &'static str,
+        address: &str,
+        options: SocketOptions,
+    ) -> io::Result<Self> {
+        options.check()?;
+
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket_type,
+            endpoint: Endpoint::Bound(listener),
+            options,
+            transport: None,
+            engine: None,
+            encoder: Encoder::default(),
+            on_connect: None,
+            nonblocking: false,
+        })
+    }
+
+    /// Open a fresh transport to the endpoint.
+    fn open(&mut self) -> Result<Transport, (Failure, io::Error)> {
+        let classify = |err: io::Error| (Failure::of_connect(&err), err);
+
+        match &mut self.endpoint {
+            Endpoint::Tcp(address) => {
+                let addrs: Vec<SocketAddr> = address
+                    .to_socket_addrs()
+                    .map_err(|err| (Failure::Refused, err))?
+                    .collect();
+
+                let stream = TcpStream::connect(&addrs[..]).map_err(classify)?;
+
+                stream
+                    .set_read_timeout(self.options.rcvtimeo())
+                    .and_then(|_| stream.set_write_timeout(self.options.sndtimeo()))
+                    .map_err(classify)?;
+
+                Ok(Transport::Tcp(stream))
+            }
+
+            Endpoint::Custom(Some(factory)) => Ok(Transport::Custom(factory().map_err(classify)?)),
+
+            Endpoint::Custom(None) => Err((
+                Failure::Refused,
+                io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "the connection was lost and there is no way to reconnect",
+                ),
+            )),
+
+            Endpoint::Bound(_) => Err((
+                Failure::Refused,
+                io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "a bound socket has no connection of its own",
+                ),
+            )),
+        }
+    }
+
+    /// Open a fresh transport and perform the handshake on it.
+    fn connect(&mut self) -> Result<(Transport, Engine), (Failure, io::Error)> {
+        let mut transport = self.open()?;
+        let mut engine = Engine::new(self.socket_type, &self.options);
+
+        handshake(&mut transport, &mut engine).map_err(|err| (Failure::Handshake, err))?;
+
+        Ok((transport, engine))
+    }
+
+    /// The options this stream was configured with.
+    #[inline]
+    pub(crate) fn options(&self) -> &SocketOptions {
+        &self.options
+    }
+
+    /// What the currently connected peer told us about itself.
+    #[inline]
+    pub(crate) fn peer(&self) -> Option<Arc<Metadata>> {
+        self.engine.as_ref()?.peer().cloned()
+    }
+
+    /// The listener of a bound stream.
+    #[inline]
+    pub(crate) fn listener(&self) -> Option<&TcpListener> {
+        match &self.endpoint {
+            Endpoint::Bound(listener) => Some(listener),
+            _ => None,
+        }
+    }
+
+    /// The address a bound stream listens on, or that the connection is made from.
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        if let Some(listener) = self.listener() {
+            return listener.local_addr();
+        }
+
+        match self.transport.as_ref() {
+            Some(Transport::Tcp(stream)) => stream.local_addr(),
+            Some(Transport::Custom(_)) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "user supplied connections have no address",
+            )),
+            None => Err(io::Error::from(io::ErrorKind::NotConnected)),
+        }
     }
 
-    pub(crate) fn ensure_connected(&mut self) -> &mut Transport {
+    /// Have `hook` called on every connection made from now on.
+    ///
+    /// Connections are made again after the peer went away, which is where
+    /// whatever the peer was told about before has to be told again.
+    pub(crate) fn on_connect<F>(&mut self, hook: F)
+    where
+        F: FnMut(&mut Engine) -> io::Result<()> + Send + 'static,
+    {
+        self.on_connect = Some(ConnectHook::new(hook));
+    }
+
+    /// Forget about the current connection, the next use will reconnect.
+    fn disconnect(&mut self) {
+        self.transport.take();
+        self.engine.take();
+    }
+
+    /// Get the current transport, (re)connecting according to the reconnect policy if needed.
+    ///
+    /// Once the policy gives up the error of the last attempt is returned.
+    pub(crate) fn ensure_connected(&mut self) -> io::Result<&mut Transport> {
+        let mut attempt = 0;
+
         while self.transport.is_none() {
-            if let Ok(fresh) = self.connect() {
-                let _ = self.transport.replace(fresh);
-                break;
-            } else {
-                std::thread::sleep(std::time::Duration::from_millis(100));
+            match self.connect() {
+                Ok((fresh, mut engine)) => {
+                    if let Some(hook) = self.on_connect.as_mut() {
+                        hook.call(&mut engine)?;
+                    }
+
+                    let _ = self.transport.replace(fresh);
+                    let _ = self.engine.replace(engine);
+                    self.nonblocking = false;
+                    break;
+                }
+
+                Err((_, err))
+                    if matches!(self.endpoint, Endpoint::Custom(None) | Endpoint::Bound(_)) =>
+                {
+                    return Err(err)
+                }
+
+                Err((failure, err)) => {
+                    attempt += 1;
+
+                    let reconnect = self.options.reconnect();
+
+                    if !reconnect.should_retry(attempt, failure) {
+                        return Err(reconnect.give_up(attempt, err));
+                    }
+
+                    std::thread::sleep(reconnect.delay(attempt));
+                }
             }
         }
 
         match self.transport.as_mut() {
-            Some(inner) => inner,
+            Some(inner) => Ok(inner),
             None => unreachable!(),
         }
     }
 
-    /// Read a frame and return a `FrameBuf` containing it.
-    #[inline]
-    pub(crate) fn recv_frame(&mut self) -> io::Result<FrameBuf> {
-        let tag = {
-            let mut tag = [0xFFu8];
-            self.read(&mut tag)?;
-            tag[0]
+    /// Switch a TCP transport in and out of non-blocking mode, if it isn't in that mode already.
+    ///
+    /// Sends are written without blocking while everything else blocks, keeping
+    /// track of the mode saves switching back and forth on every call.
+    fn set_nonblocking(&mut self, nonblocking: bool) -> io::Result<()> {
+        if self.nonblocking != nonblocking {
+            if let Some(Transport::Tcp(stream)) = self.transport.as_ref() {
+                stream.set_nonblocking(nonblocking)?;
+            }
+
+            self.nonblocking = nonblocking;
+        }
+
+        Ok(())
+    }
+
+    /// Get the current transport in blocking mode, (re)connecting if needed.
+    fn blocking_transport(&mut self) -> io::Result<&mut Transport> {
+        self.ensure_connected()?;
+        self.set_nonblocking(false)?;
+
+        match self.transport.as_mut() {
+            Some(transport) => Ok(transport),
+            None => unreachable!(),
+        }
+    }
+
+    /// Write out as much of the engine's queued output as the peer takes without blocking.
+    ///
+    /// User supplied connections can't be made non-blocking so they are written to in full.
+    fn try_flush_output(&mut self) -> io::Result<()> {
+        if let Some(Transport::Custom(_)) = self.transport {
+            return self.flush_output();
+        }
+
+        self.set_nonblocking(true)?;
+
+        if let (Some(transport), Some(engine)) = (self.transport.as_mut(), self.engine.as_mut()) {
+            while !engine.output().is_empty() {
+                match transport.write(engine.output()) {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                    Ok(n) => engine.consume_output(n),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write out everything the engine has queued up for the peer.
+    ///
+    /// Output is consumed after every write so that when the send timeout
+    /// cuts this short what the peer already got isn't sent again.
+    fn flush_output(&mut self) -> io::Result<()> {
+        self.blocking_transport()?;
+
+        let (transport, engine) = match (self.transport.as_mut(), self.engine.as_mut()) {
+            (Some(transport), Some(engine)) => (transport, engine),
+            _ => unreachable!(),
+        };
+
+        while !engine.output().is_empty() {
+            match transport.write(engine.output()) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => engine.consume_output(n),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Subscribe the peer to `topic`, or with `subscribe` unset cancel that subscription.
+    pub(crate) fn write_subscription(&mut self, topic: &[u8], subscribe: bool) -> io::Result<()> {
+        self.ensure_connected()?;
+
+        if let Some(engine) = self.engine.as_mut() {
+            engine.write_subscription(topic, subscribe)?;
+        }
+
+        self.flush_output()
+    }
+
+    /// Queue up a multi-part message for the peer and write out what we can without blocking.
+    ///
+    /// Once the SNDHWM is reached this blocks until the peer catches up,
+    /// or drops the message, depending on the socket type.
+    pub(crate) fn send(&mut self, message: Message<'_>) -> io::Result<()> {
+        self.ensure_connected()?;
+
+        if self
+            .engine
+            .as_ref()
+            .is_some_and(|engine| engine.output().is_empty())
+        {
+            return self.send_vectored(message);
+        }
+
+        loop {
+            let queued = match self.engine.as_mut() {
+                Some(engine) => engine.write_message(message),
+                None => unreachable!(),
+            };
+
+            match queued {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => self.flush_output()?,
+                queued => break queued?,
+            }
+        }
+
+        self.try_flush_output()
+    }
+
+    /// Write `message` straight from its buffer with vectored writes, nothing may be queued up.
+    ///
+    /// Whatever the peer doesn't take without blocking is queued up in the engine.
+    fn send_vectored(&mut self, message: Message<'_>) -> io::Result<()> {
+        let custom = matches!(self.transport, Some(Transport::Custom(_)));
+
+        self.set_nonblocking(!custom)?;
+
+        let (transport, engine) = match (self.transport.as_mut(), self.engine.as_mut()) {
+            (Some(transport), Some(engine)) => (transport, engine),
+            _ => unreachable!(),
+        };
+
+        let mut slices = self.encoder.encode(message)?;
+        let mut remainder = &mut slices[..];
+
+        while !remainder.is_empty() {
+            match transport.write_vectored(remainder) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => IoSlice::advance_slices(&mut remainder, n),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        engine.write_remainder(remainder);
+
+        Ok(())
+    }
+
+    /// Flush queued up messages for up to the linger period and hang up.
+    ///
+    /// TCP connections are shut down cleanly: after our side is closed we
+    /// briefly wait for the peer to hang up so that nothing still in flight
+    /// gets reset. This never reconnects, if there's no connection there's
+    /// nothing to flush.
+    ///
+    /// Fails with `TimedOut` if the linger period expired with messages
+    /// still queued, those are discarded.
+    pub(crate) fn close(&mut self) -> io::Result<()> {
+        self.set_nonblocking(false)?;
+
+        let (transport, mut engine) = match (self.transport.take(), self.engine.take()) {
+            (Some(transport), Some(engine)) => (transport, engine),
+            _ => return Ok(()),
         };
 
-        let (size, offset) = match tag {
-            0x0 | 0x1 | 0x4 => {
-                let mut tag = [0xFFu8];
-                self.read(&mut tag)?;
-                (tag[0] as usize, 2)
+        match transport {
+            Transport::Tcp(mut stream) => {
+                let deadline = self.options.linger().map(|linger| Instant::now() + linger);
+
+                linger(&mut stream, &mut engine, deadline)
+            }
+
+            // There's no timing out on user supplied connections.
+            Transport::Custom(mut conn) => match self.options.linger() {
+                Some(linger) if linger.is_zero() => Ok(()),
+                _ => {
+                    conn.write_all(engine.output())?;
+                    conn.flush()
+                }
+            },
+        }
+    }
+
+    /// Receive a whole message into `message`, reusing its storage.
+    ///
+    /// Reads go straight into the engine's input buffer, pulling in as much
+    /// as the peer has available so that every message in it is decoded
+    /// without further syscalls. Parts are copied from there into `message`
+    /// so once it's big enough this doesn't allocate. If the peer hangs up
+    /// we reconnect and start over with a fresh message. Commands that come
+    /// in between messages are passed on to `handler`.
+    pub(crate) fn recv_into<'b>(
+        &mut self,
+        message: &'b mut MessageBuf,
+        handler: &mut dyn Handler,
+    ) -> io::Result<Message<'b>> {
+        loop {
+            self.ensure_connected()?;
+
+            if self.engine.as_ref().is_some_and(Engine::conflates) {
+                self.drain()?;
             }
 
-            0x2 | 0x3 | 0x6 => {
-                let mut head = [0; 8];
-                self.read(&mut head)?;
-                (u64::from_be_bytes(head) as usize, 9)
+            let accept = &mut |head: &[u8]| handler.accepts(head);
+
+            match self.engine.as_mut() {
+                Some(engine) => match engine.poll_into_filtered(message, accept) {
+                    Ok(Some(Event::Message)) => break,
+                    Ok(Some(Event::Command(command))) => {
+                        handler.command(command)?;
+                        continue;
+                    }
+                    Ok(_) => (),
+
+                    // There's no telling where the next frame starts after the peer broke protocol.
+                    Err(err) => {
+                        self.disconnect();
+                        return Err(err);
+                    }
+                },
+                None => unreachable!(),
             }
 
-            _ => return Err(io::Error::from(io::ErrorKind::InvalidData)),
+            self.fill()?;
+        }
+
+        self.flush_answers()?;
+
+        message.set_metadata(self.peer());
+
+        Ok(message.as_message())
+    }
+
+    /// Start receiving the next frame, its body is read through the returned `FrameReader`.
+    ///
+    /// Unlike `recv_into` the frame doesn't have to fit in memory, only its
+    /// header is read up front. Commands in between are passed on to `handler`.
+    pub(crate) fn frame_reader(
+        &mut self,
+        handler: &mut dyn Handler,
+    ) -> io::Result<FrameReader<'_>> {
+        let (kind, remaining) = loop {
+            self.ensure_connected()?;
+
+            match self.engine.as_mut() {
+                Some(engine) => match engine.poll_header() {
+                    Ok(Some(Event::Header(kind, remaining))) => break (kind, remaining),
+
+                    Ok(Some(Event::Command(command))) => {
+                        handler.command(command)?;
+                        continue;
+                    }
+
+                    Ok(_) => (),
+
+                    // There's no telling where the next frame starts after the peer broke protocol.
+                    Err(err) => {
+                        self.disconnect();
+                        return Err(err);
+                    }
+                },
+                None => unreachable!(),
+            }
+
+            self.fill()?;
         };
 
-        let mut raw_frame = Vec::with_capacity(size + 2);
+        self.flush_answers()?;
+
+        Ok(FrameReader {
+            stream: self,
+            remaining,
+            more: kind == FrameKind::MessagePart,
+        })
+    }
+
+    /// Start sending a message a part at a time through the returned `MessageWriter`.
+    ///
+    /// Everything queued up is written out first, the parts then go
+    /// straight to the peer.
+    pub(crate) fn message_writer(&mut self) -> io::Result<MessageWriter<'_>> {
+        self.flush_output()?;
+
+        Ok(MessageWriter {
+            stream: self,
+            finished: false,
+        })
+    }
+
+    /// Write out whatever the engine queued up while receiving, like answers to PINGs, without blocking.
+    fn flush_answers(&mut self) -> io::Result<()> {
+        match self.engine.as_ref() {
+            Some(engine) if !engine.output().is_empty() => self.try_flush_output(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read whatever the peer sent so far into the engine without blocking, so the latest of it can be taken.
+    ///
+    /// A peer that sends faster than it can be drained gets cut off after
+    /// `MAX_DRAIN` bytes. User supplied connections can't be drained since
+    /// they can't be made non-blocking.
+    fn drain(&mut self) -> io::Result<()> {
+        if !matches!(self.transport, Some(Transport::Tcp(_))) {
+            return Ok(());
+        }
+
+        self.set_nonblocking(true)?;
+
+        if let (Some(transport), Some(engine)) = (self.transport.as_mut(), self.engine.as_mut()) {
+            let mut drained = 0;
 
-        raw_frame.push(tag);
+            while drained < MAX_DRAIN {
+                let read = transport.read(engine.read_buf(READ_CHUNK));
+                engine.commit(*read.as_ref().unwrap_or(&0));
+
+                match read {
+                    // The next fill finds out that the peer hung up.
+                    Ok(0) => break,
+                    Ok(n) => drained += n,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read whatever the peer has available into the engine.
+    ///
+    /// Reads are sized by [`Engine::read_size`], so a large frame takes a
+    /// few of them but room is only ever made for bytes that arrived. If
+    /// the peer hung up we disconnect, the next use will reconnect.
+    fn fill(&mut self) -> io::Result<()> {
+        // Whatever is still queued up may be what the peer is waiting on, this also
+        // puts the transport back in blocking mode.
+        self.flush_output()?;
+
+        let n = match (self.transport.as_mut(), self.engine.as_mut()) {
+            (Some(transport), Some(engine)) => {
+                let read = transport.read(engine.read_buf(engine.read_size()));
+                engine.commit(*read.as_ref().unwrap_or(&0));
+                read?
+            }
 
-        match offset {
-            2 => raw_frame.push(size as u8),
-            9 => raw_frame.extend_from_slice(&u64::to_be_bytes(size as u64)),
             _ => unreachable!(),
+        };
+
+        if n == 0 {
+            self.disconnect();
         }
 
-        if size > 0 {
-            let mut bytes = self.bytes();
+        Ok(())
+    }
+
+    /// The transport of the current connection in blocking mode, without reconnecting.
+    fn connected_transport(&mut self) -> io::Result<&mut Transport> {
+        self.set_nonblocking(false)?;
+
+        self.transport
+            .as_mut()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))
+    }
+}
+
+// -- FrameReader<'a>
+
+/// Reads the body of an incoming frame straight off of the connection.
+///
+/// Meant for frames too large to be held in memory, see
+/// [`Pull::frame_reader`](crate::Pull::frame_reader). Whatever is left of
+/// the body once this is dropped is skipped over.
+pub struct FrameReader<'a> {
+    stream: &'a mut Stream,
+    remaining: u64,
+    more: bool,
+}
+
+impl FrameReader<'_> {
+    /// How much of the body is left to be read.
+    #[inline]
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Whether more parts of the message follow this one.
+    #[inline]
+    pub fn more(&self) -> bool {
+        self.more
+    }
+}
+
+impl fmt::Debug for FrameReader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameReader")
+            .field("remaining", &self.remaining)
+            .field("more", &self.more)
+            .finish()
+    }
+}
+
+impl Read for FrameReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.remaining.min(buf.len() as u64) as usize;
+
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let buf = &mut buf[..len];
+
+        // Whatever of the body was read along with the header comes first.
+        let n = match self.stream.engine.as_mut() {
+            Some(engine) => engine.read_body(buf),
+            None => return Err(io::Error::from(io::ErrorKind::NotConnected)),
+        };
 
-            for _ in 0..size {
-                let byte = bytes.next().ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))??;
-                raw_frame.push(byte)
+        let n = match n {
+            0 => match self.stream.connected_transport()?.read(buf)? {
+                0 => {
+                    self.stream.disconnect();
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+
+                n => n,
+            },
+
+            n => n,
+        };
+
+        self.remaining -= n as u64;
+
+        Ok(n)
+    }
+}
+
+impl Drop for FrameReader<'_> {
+    fn drop(&mut self) {
+        if let Some(engine) = self.stream.engine.as_mut() {
+            engine.skip(self.remaining);
+        }
+    }
+}
+
+// -- MessageWriter<'a>
+
+/// Writes a message straight to the connection, a part at a time.
+///
+/// Meant for parts too large to be held in memory, see
+/// [`Push::message_writer`](crate::Push::message_writer). A message can't
+/// be taken back once it's partly written so if this is dropped before the
+/// last part has been written the connection is dropped too.
+pub struct MessageWriter<'a> {
+    stream: &'a mut Stream,
+    finished: bool,
+}
+
+impl MessageWriter<'_> {
+    /// Write a part of exactly `size` bytes read from `source`, `more` being whether other parts follow.
+    ///
+    /// If `source` runs out early the connection is dropped and this fails
+    /// with `UnexpectedEof`.
+    pub fn write_part<R>(&mut self, size: u64, source: R, more: bool) -> io::Result<()>
+    where
+        R: Read,
+    {
+        if self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the last part of the message has already been written",
+            ));
+        }
+
+        let kind = match more {
+            true => FrameKind::MessagePart,
+            false => FrameKind::MessageTail,
+        };
+
+        let size_usize =
+            usize::try_from(size).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        let mut header = Vec::with_capacity(9);
+        encode_header(kind, size_usize, &mut header);
+
+        let written = self.stream.connected_transport().and_then(|transport| {
+            transport.write_all(&header)?;
+
+            match io::copy(&mut source.take(size), transport)? {
+                copied if copied < size => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                _ => Ok(()),
             }
+        });
+
+        if written.is_err() {
+            self.stream.disconnect();
         }
 
-        let frame_buf = FrameBuf::new(raw_frame);
-        Ok(frame_buf)
+        self.finished = written.is_err() || !more;
+
+        written
+    }
+}
+
+impl fmt::Debug for MessageWriter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageWriter")
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+impl Drop for MessageWriter<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.stream.disconnect();
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.close();
     }
 }
 
@@ -151,11 +1020,11 @@ impl Read for Stream {
         let mut n_bytes;
 
         while {
-            n_bytes = self.ensure_connected().read(buf)?;
+            n_bytes = self.blocking_transport()?.read(buf)?;
             n_bytes
-        } <= 0
+        } == 0
         {
-            self.transport.take();
+            self.disconnect();
         }
 
         Ok(n_bytes)
@@ -164,10 +1033,10 @@ impl Read for Stream {
 
 impl Write for Stream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.ensure_connected().write(buf)
+        self.blocking_transport()?.write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.ensure_connected().flush()
+        self.blocking_transport()?.flush()
     }
 }
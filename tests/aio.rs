@@ -0,0 +1,97 @@
+//! Async sockets keep the ZMTP stream intact when their futures are cut short.
+
+#![cfg(feature = "tokio")]
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::{greeting, ready};
+
+/// Big enough for a message to take several writes to go out.
+const SIZE: usize = 1 << 22;
+
+/// Split what a peer sent after its greeting into message bodies, skipping commands.
+fn messages(mut bytes: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut messages = vec![];
+
+    while let Some((&flags, rest)) = bytes.split_first() {
+        if flags & !0x6 != 0 {
+            return Err(format!("unexpected flags {:#x}", flags));
+        }
+
+        let (size, rest) = match flags & 0x2 {
+            0 => (*rest.first().ok_or("cut short")? as usize, &rest[1..]),
+            _ => {
+                let size = rest.get(..8).ok_or("cut short")?.try_into().unwrap();
+                (u64::from_be_bytes(size) as usize, &rest[8..])
+            }
+        };
+
+        let body = rest.get(..size).ok_or("cut short")?;
+
+        if flags & 0x4 == 0 {
+            messages.push(body.to_vec());
+        }
+
+        bytes = &rest[size..];
+    }
+
+    Ok(messages)
+}
+
+#[tokio::test]
+async fn timed_out_sends_do_not_resend_what_went_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    // A slow PULL peer lets sends get part of the queue out before timing out.
+    let peer = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.write_all(&greeting(1)).unwrap();
+        conn.write_all(&ready("PULL")).unwrap();
+
+        let mut received = vec![];
+        let mut chunk = [0; 1 << 16];
+
+        loop {
+            match conn.read(&mut chunk).unwrap() {
+                0 => break,
+                n => received.extend_from_slice(&chunk[..n]),
+            }
+
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        messages(&received[64..])
+    });
+
+    let options = SocketOptions::default()
+        .with(SocketOption::SndHwm(1))
+        .with(SocketOption::SndTimeo(Some(Duration::from_millis(2))))
+        .with(SocketOption::Linger(Some(Duration::from_secs(10))));
+
+    let mut push: aio::Push = aio::connect_with("tcp", &address, options).await.unwrap();
+
+    let mut sent = vec![];
+
+    for n in 0..64u8 {
+        match push.send(vec![n; SIZE]).await {
+            Ok(()) => sent.push(vec![n; SIZE]),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        }
+    }
+
+    assert!(sent.len() < 64, "the peer never fell behind");
+
+    push.close().await.unwrap();
+
+    // Comparing megabytes, spare the output if they differ.
+    assert!(peer.join().unwrap().unwrap() == sent);
+}
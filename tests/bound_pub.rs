@@ -0,0 +1,330 @@
+//! A bound PUB socket filters and fans out messages to its subscribers itself.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::{greeting, ready, SIZE};
+
+fn bound() -> (Pub, String) {
+    let publisher: Pub = zedmq::socket().bind("tcp://127.0.0.1:0").unwrap();
+    let endpoint = format!("tcp://{}", publisher.local_addr().unwrap());
+
+    (publisher, endpoint)
+}
+
+/// Connect to `endpoint` as a raw ZMTP 3.0 peer of `socket_type`, going as far as sending READY.
+fn raw_peer(endpoint: &str, socket_type: &str) -> TcpStream {
+    let mut peer = TcpStream::connect(endpoint.trim_start_matches("tcp://")).unwrap();
+
+    peer.write_all(&greeting(0)).unwrap();
+    peer.write_all(&ready(socket_type)).unwrap();
+
+    peer
+}
+
+/// Keep publishing `message` until `socket` receives something.
+fn publish_until_received(publisher: &mut Pub, message: &[u8], socket: &zmq::Socket) -> Vec<u8> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    socket.set_rcvtimeo(10).unwrap();
+
+    while Instant::now() < deadline {
+        publisher.send(message).unwrap();
+
+        if let Ok(received) = socket.recv_bytes(0) {
+            return received;
+        }
+    }
+
+    panic!("nothing was received");
+}
+
+#[test]
+fn libzmq_subscribers() {
+    let (mut publisher, endpoint) = bound();
+
+    let ctx = zmq::Context::new();
+    let sub = ctx.socket(zmq::SUB).unwrap();
+    sub.connect(&endpoint).unwrap();
+    sub.set_subscribe(b"t").unwrap();
+
+    assert_eq!(
+        publish_until_received(&mut publisher, b"tick", &sub),
+        b"tick"
+    );
+}
+
+#[test]
+fn only_matching_messages_are_sent() {
+    let (mut publisher, endpoint) = bound();
+
+    // Unlike a SUB socket an XSUB socket doesn't filter, it gets whatever is sent to it.
+    let ctx = zmq::Context::new();
+    let a = ctx.socket(zmq::XSUB).unwrap();
+    let b = ctx.socket(zmq::XSUB).unwrap();
+
+    for (xsub, topic) in [(&a, b"\x01a"), (&b, b"\x01b")] {
+        xsub.connect(&endpoint).unwrap();
+        xsub.send(&topic[..], 0).unwrap();
+    }
+
+    for _ in 0..3 {
+        assert_eq!(publish_until_received(&mut publisher, b"a1", &a), b"a1");
+        assert_eq!(publish_until_received(&mut publisher, b"b1", &b), b"b1");
+    }
+}
+
+#[test]
+fn inverted_matching() {
+    let mut publisher: Pub = zedmq::socket()
+        .option(SocketOption::InvertMatching(true))
+        .bind("tcp://127.0.0.1:0")
+        .unwrap();
+
+    let endpoint = format!("tcp://{}", publisher.local_addr().unwrap());
+
+    let ctx = zmq::Context::new();
+    let xsub = ctx.socket(zmq::XSUB).unwrap();
+    xsub.connect(&endpoint).unwrap();
+    xsub.send(&b"\x01a"[..], 0).unwrap();
+
+    // Until the subscription comes in everything matches, give it the time to.
+    publish_until_received(&mut publisher, b"b0", &xsub);
+    std::thread::sleep(Duration::from_millis(100));
+    while xsub.recv_bytes(zmq::DONTWAIT).is_ok() {}
+
+    for _ in 0..3 {
+        publisher.send(&b"a1"[..]).unwrap();
+        assert_eq!(publish_until_received(&mut publisher, b"b1", &xsub), b"b1");
+    }
+}
+
+#[test]
+fn slow_subscribers_do_not_block() {
+    let (mut publisher, endpoint) = bound();
+
+    // A subscriber to everything that never reads a thing.
+    let mut slow = raw_peer(&endpoint, "SUB");
+    slow.write_all(b"\x00\x01\x01").unwrap();
+
+    let ctx = zmq::Context::new();
+    let sub = ctx.socket(zmq::SUB).unwrap();
+    sub.connect(&endpoint).unwrap();
+    sub.set_subscribe(b"").unwrap();
+
+    publish_until_received(&mut publisher, b"warm up", &sub);
+
+    let large = vec![b'x'; 64 * 1024];
+
+    for _ in 0..1000 {
+        publisher.send(&large[..]).unwrap();
+    }
+
+    // The publisher got through all of that, and those keeping up still get messages.
+    assert!(!publish_until_received(&mut publisher, b"done", &sub).is_empty());
+}
+
+#[test]
+fn repeated_subscriptions_are_cancelled_together() {
+    let (mut publisher, endpoint) = bound();
+    let (caught_up, done) = mpsc::channel();
+
+    let subscriber = thread::spawn(move || {
+        let mut sub: Sub = zedmq::socket()
+            .option(SocketOption::RcvTimeo(Some(Duration::from_secs(5))))
+            .connect(&endpoint)
+            .unwrap();
+
+        sub.subscribe(b"a").unwrap();
+        sub.subscribe(b"a").unwrap();
+        sub.unsubscribe(b"a").unwrap();
+        sub.unsubscribe(b"a").unwrap();
+        sub.subscribe(b"b").unwrap();
+
+        // Once a "b" arrives the publisher has seen everything before its subscription.
+        while sub.recv_unchecked().unwrap()[0] != b"b"[..] {}
+        caught_up.send(()).unwrap();
+
+        let mut leaked = 0;
+
+        loop {
+            match &sub.recv_unchecked().unwrap()[0][..] {
+                b"b-end" => return leaked,
+                b"b" => {}
+                _ => leaked += 1,
+            }
+        }
+    });
+
+    while let Err(mpsc::TryRecvError::Empty) = done.try_recv() {
+        publisher.send(&b"a-leak"[..]).unwrap();
+        publisher.send(&b"b"[..]).unwrap();
+    }
+
+    for _ in 0..100 {
+        publisher.send(&b"a-leak"[..]).unwrap();
+    }
+
+    publisher.send(&b"b-end"[..]).unwrap();
+    publisher.close().unwrap();
+
+    assert_eq!(subscriber.join().unwrap(), 0);
+}
+
+#[test]
+fn peers_turned_away_are_told_why() {
+    let (mut publisher, endpoint) = bound();
+
+    let mut peer = raw_peer(&endpoint, "PUSH");
+    peer.set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut received = vec![];
+    let mut buf = [0u8; 512];
+
+    // The publisher hangs up once it's done with the peer.
+    loop {
+        assert!(Instant::now() < deadline, "never hung up");
+        publisher.poll().unwrap();
+
+        match peer.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(_) => (),
+        }
+    }
+
+    assert!(received.ends_with(b"\x04\x1A\x05ERROR\x13invalid socket type"));
+}
+
+#[test]
+fn polling_takes_on_peers_without_sending() {
+    let (mut publisher, endpoint) = bound();
+
+    let (connected, done) = mpsc::channel();
+
+    // Connecting only returns once the handshake is done.
+    let subscriber = thread::spawn(move || {
+        let sub: Sub = zedmq::socket().connect(&endpoint).unwrap();
+        connected.send(()).unwrap();
+        drop(sub);
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    while let Err(mpsc::TryRecvError::Empty) = done.try_recv() {
+        assert!(Instant::now() < deadline, "never got through the handshake");
+        publisher.poll().unwrap();
+    }
+
+    subscriber.join().unwrap();
+}
+
+#[test]
+fn flooding_peers_do_not_stall_sending() {
+    let (mut publisher, endpoint) = bound();
+
+    // Cancelling what was never subscribed to, as fast as it can.
+    let mut flood = raw_peer(&endpoint, "SUB");
+    let cancels = b"\x04\x08\x06CANCELz".repeat(8192);
+
+    thread::spawn(move || while flood.write_all(&cancels).is_ok() {});
+
+    let (sent, done) = mpsc::channel();
+
+    thread::spawn(move || {
+        for _ in 0..20 {
+            publisher.send(&b"x"[..]).unwrap();
+        }
+
+        // Nor does it stall hanging up.
+        drop(publisher);
+        sent.send(()).unwrap();
+    });
+
+    done.recv_timeout(Duration::from_secs(10)).unwrap();
+}
+
+#[test]
+fn close_flushes_what_subscribers_are_owed() {
+    let (mut publisher, endpoint) = bound();
+
+    let ctx = zmq::Context::new();
+    let sub = ctx.socket(zmq::SUB).unwrap();
+    sub.set_rcvhwm(1).unwrap();
+    sub.set_rcvbuf(4096).unwrap();
+    sub.connect(&endpoint).unwrap();
+    sub.set_subscribe(b"").unwrap();
+
+    publish_until_received(&mut publisher, b"warm up", &sub);
+
+    for n in 1..=4u8 {
+        publisher.send(vec![n; SIZE]).unwrap();
+    }
+
+    let receiver = thread::spawn(move || {
+        sub.set_rcvtimeo(5000).unwrap();
+
+        let mut received = vec![];
+
+        while received.len() < 4 {
+            match sub.recv_bytes(0) {
+                Ok(message) if message.len() == SIZE => received.push(message[0]),
+                Ok(_) => (),
+                Err(_) => break,
+            }
+        }
+
+        received
+    });
+
+    publisher.close().unwrap();
+
+    assert_eq!(receiver.join().unwrap(), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn close_gives_up_on_subscribers_after_the_linger_period() {
+    let mut publisher: Pub = zedmq::socket()
+        .option(SocketOption::Linger(Some(Duration::from_millis(100))))
+        .bind("tcp://127.0.0.1:0")
+        .unwrap();
+
+    let endpoint = format!("tcp://{}", publisher.local_addr().unwrap());
+
+    let mut slow = raw_peer(&endpoint, "SUB");
+    slow.write_all(b"\x00\x01\x01").unwrap();
+    slow.set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+
+    let large = vec![b'x'; 1 << 20];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut received = 0;
+    let mut buf = [0u8; 4096];
+
+    // Past the greeting and READY it's the messages coming in, then stop reading.
+    while received < 4096 {
+        assert!(Instant::now() < deadline, "never subscribed");
+        publisher.send(&large[..]).unwrap();
+        received += slow.read(&mut buf).unwrap_or(0);
+    }
+
+    for _ in 0..16 {
+        publisher.send(&large[..]).unwrap();
+    }
+
+    let started = Instant::now();
+    let err = publisher.close().unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
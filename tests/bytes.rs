@@ -0,0 +1,103 @@
+//! With the `bytes` feature shared parts are sent and received without being copied.
+
+#![cfg(feature = "bytes")]
+
+use std::io::{self, IoSlice, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use zedmq::prelude::*;
+
+mod common;
+
+use common::{pull_peer, push_peer, Playback};
+
+/// A connection that notes down where each buffer it's asked to write lives.
+struct Spy {
+    peer: Playback,
+    written_from: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Read for Spy {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.peer.read(buf)
+    }
+}
+
+impl Write for Spy {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.written_from
+            .lock()
+            .unwrap()
+            .extend(bufs.iter().map(|buf| buf.as_ptr() as usize));
+
+        bufs.iter().map(|buf| self.peer.write(buf)).sum()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.peer.flush()
+    }
+}
+
+#[test]
+fn shared_parts_are_sent_from_where_they_are() {
+    let written_from = Arc::default();
+    let peer = pull_peer(b"");
+    let written = peer.written();
+
+    let mut push: Push = zedmq::from_stream(Spy {
+        peer,
+        written_from: Arc::clone(&written_from),
+    })
+    .unwrap();
+
+    let payload = Bytes::from(vec![7; 1000]);
+    push.send(vec![Bytes::from_static(b"topic"), payload.clone()])
+        .unwrap();
+
+    assert!(written_from
+        .lock()
+        .unwrap()
+        .contains(&(payload.as_ptr() as usize)));
+
+    let mut frame = b"\x02\x00\x00\x00\x00\x00\x00\x03\xE8".to_vec();
+    frame.extend_from_slice(&payload);
+
+    assert!(written.lock().unwrap().ends_with(&frame));
+}
+
+#[test]
+fn received_parts_are_slices_of_one_buffer() {
+    let mut pull: Pull = zedmq::from_stream(push_peer(b"\x01\x05hello\x00\x05world")).unwrap();
+
+    let message = pull.recv().unwrap();
+    let hello = message.get_bytes(0).unwrap();
+    let world = message.get_bytes(1).unwrap();
+
+    assert_eq!((&hello[..], &world[..]), (&b"hello"[..], &b"world"[..]));
+
+    // Handing the parts out doesn't copy them, and they sit right where they were read.
+    assert_eq!(hello.as_ptr(), message[0].as_ptr());
+    assert_eq!(world.as_ptr() as usize, hello.as_ptr() as usize + 5 + 2);
+}
+
+#[test]
+fn shared_parts_reach_libzmq() {
+    let ctx = zmq::Context::new();
+    let pull = ctx.socket(zmq::PULL).unwrap();
+    pull.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = pull.get_last_endpoint().unwrap().unwrap();
+    let mut push: Push = zedmq::socket().connect(&endpoint).unwrap();
+
+    let parts = vec![Bytes::from_static(b"a"), Bytes::from(vec![b'b'; 100_000])];
+    push.send(parts.clone()).unwrap();
+
+    let received = pull.recv_multipart(0).unwrap();
+
+    assert!(received == parts.iter().map(|part| part.to_vec()).collect::<Vec<_>>());
+}
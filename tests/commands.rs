@@ -0,0 +1,113 @@
+//! Commands in the handshake and in between messages.
+
+use std::io::{self, Read};
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::{peer, push_peer};
+
+/// A long command frame with the body `body`.
+fn long_command(body: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x6];
+    frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    frame.extend_from_slice(body);
+    frame
+}
+
+#[test]
+fn long_ready_is_understood() {
+    let mut ready = b"\x05READY\x0BSocket-Type\x00\x00\x00\x04PUSH".to_vec();
+    ready.extend_from_slice(b"\x05X-Pad\x00\x00\x01\x2C");
+    ready.extend_from_slice(&[b'x'; 300]);
+
+    let mut frames = long_command(&ready);
+    frames.extend_from_slice(b"\x00\x02ok");
+
+    let mut pull: Pull = zedmq::from_stream(peer(&frames)).unwrap();
+
+    let message = pull.recv().unwrap();
+    let metadata = message.metadata().unwrap();
+
+    assert_eq!(message, vec![b"ok".to_vec()]);
+    assert_eq!(metadata.socket_type(), Some("PUSH"));
+    assert_eq!(metadata.get("x-pad"), Some(&[b'x'; 300][..]));
+}
+
+#[test]
+fn truncated_ready_is_refused() {
+    let ready = b"\x05READY\x0BSocket-Type\x00\x00\x00\x40PUSH";
+
+    let result = zedmq::from_stream::<Pull, _>(peer(&long_command(ready)));
+
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn long_ready_is_sent() {
+    let ctx = zmq::Context::new();
+    let router = ctx.socket(zmq::ROUTER).unwrap();
+    router.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = router.get_last_endpoint().unwrap().unwrap();
+    let id = vec![b'i'; 255];
+
+    let req: Req = zedmq::socket()
+        .option(SocketOption::RoutingId(id.clone()))
+        .connect(&endpoint)
+        .unwrap();
+
+    let _pending = req.send("hello").unwrap();
+
+    let message = router.recv_multipart(0).unwrap();
+    assert_eq!(message.first(), Some(&id));
+    assert_eq!(message.last().map(Vec::as_slice), Some(&b"hello"[..]));
+}
+
+#[test]
+fn commands_in_between_messages() {
+    let mut frames = b"\x00\x01a".to_vec();
+    frames.extend_from_slice(b"\x04\x09\x04PING\x00\x0Ahi");
+    frames.extend_from_slice(b"\x00\x01b");
+    frames.extend_from_slice(b"\x04\x0B\x09SUBSCRIBEx");
+    frames.extend_from_slice(b"\x04\x04\x03FOO");
+    frames.extend_from_slice(b"\x04\x05\x04PONG");
+    frames.extend_from_slice(b"\x00\x01c");
+
+    let peer = push_peer(&frames);
+    let written = peer.written();
+
+    let mut pull: Pull = zedmq::from_stream(peer).unwrap();
+
+    for body in ["a", "b", "c"] {
+        assert_eq!(pull.recv().unwrap(), vec![body.as_bytes().to_vec()]);
+    }
+
+    assert!(written.lock().unwrap().ends_with(b"\x04\x07\x04PONGhi"));
+}
+
+#[test]
+fn commands_before_streamed_frames() {
+    let mut frames = b"\x04\x07\x04PING\x00\x00".to_vec();
+    frames.extend_from_slice(b"\x00\x05hello");
+
+    let mut pull: Pull = zedmq::from_stream(push_peer(&frames)).unwrap();
+
+    let mut body = String::new();
+    pull.frame_reader()
+        .unwrap()
+        .read_to_string(&mut body)
+        .unwrap();
+
+    assert_eq!(body, "hello");
+}
+
+#[test]
+fn handshake_commands_after_the_handshake_are_refused() {
+    let ready = b"\x04\x1A\x05READY\x0BSocket-Type\x00\x00\x00\x04PUSH";
+
+    let mut pull: Pull = zedmq::from_stream(push_peer(ready)).unwrap();
+
+    assert_eq!(pull.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
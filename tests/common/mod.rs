@@ -0,0 +1,108 @@
+//! Peers played back over a user supplied connection, and libzmq peers, shared by the integration
+//! tests.
+
+#![allow(dead_code)]
+
+use std::io::{self, Cursor, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A connection that plays back what a peer would have sent and records what's written to it.
+pub struct Playback(Cursor<Vec<u8>>, Arc<Mutex<Vec<u8>>>, Arc<AtomicUsize>);
+
+impl Playback {
+    /// Where to find what was written to the connection, even once it's been handed over.
+    pub fn written(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.1.clone()
+    }
+
+    /// How much of what the peer sent has been read so far, even once it's been handed over.
+    pub fn taken(&self) -> Arc<AtomicUsize> {
+        self.2.clone()
+    }
+}
+
+impl Read for Playback {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        self.2.fetch_add(n, Ordering::SeqCst);
+        Ok(n)
+    }
+}
+
+impl Write for Playback {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.1.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The greeting of a peer speaking ZMTP 3.`minor` with the NULL mechanism.
+pub fn greeting(minor: u8) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0x7F, 3, minor];
+    bytes.extend_from_slice(b"NULL");
+    bytes.resize(64, 0);
+    bytes
+}
+
+/// A READY command frame for a `socket_type` socket.
+pub fn ready(socket_type: &str) -> Vec<u8> {
+    let mut body = b"\x05READY\x0BSocket-Type\x00\x00\x00".to_vec();
+    body.push(socket_type.len() as u8);
+    body.extend_from_slice(socket_type.as_bytes());
+
+    let mut frame = vec![0x4, body.len() as u8];
+    frame.extend_from_slice(&body);
+    frame
+}
+
+/// A ZMTP 3.0 peer's greeting followed by `frames`.
+pub fn peer(frames: &[u8]) -> Playback {
+    let mut bytes = greeting(0);
+    bytes.extend_from_slice(frames);
+
+    Playback(Cursor::new(bytes), Arc::default(), Arc::default())
+}
+
+/// A ZMTP 3.`minor` `socket_type` peer that sends `frames` once the handshake is done.
+pub fn peer_of(socket_type: &str, minor: u8, frames: &[u8]) -> Playback {
+    let mut bytes = greeting(minor);
+    bytes.extend_from_slice(&ready(socket_type));
+    bytes.extend_from_slice(frames);
+
+    Playback(Cursor::new(bytes), Arc::default(), Arc::default())
+}
+
+/// A PUSH peer that sends `frames` once the handshake is done.
+pub fn push_peer(frames: &[u8]) -> Playback {
+    peer_of("PUSH", 0, frames)
+}
+
+/// A PULL peer that sends `frames` once the handshake is done.
+pub fn pull_peer(frames: &[u8]) -> Playback {
+    peer_of("PULL", 0, frames)
+}
+
+/// A PUB peer speaking ZMTP 3.`minor` that sends `frames` once the handshake is done.
+pub fn pub_peer(minor: u8, frames: &[u8]) -> Playback {
+    peer_of("PUB", minor, frames)
+}
+
+/// Big enough for a message to fill up the socket buffers.
+pub const SIZE: usize = 1 << 22;
+
+/// A libzmq socket of `kind` that's bound and holds on to as little as it can.
+pub fn bound(ctx: &zmq::Context, kind: zmq::SocketType) -> (zmq::Socket, String) {
+    let socket = ctx.socket(kind).unwrap();
+    socket.set_rcvhwm(1).unwrap();
+    socket.set_rcvbuf(4096).unwrap();
+    socket.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = socket.get_last_endpoint().unwrap().unwrap();
+
+    (socket, endpoint)
+}
@@ -0,0 +1,57 @@
+//! Conflating sockets only take the latest message that came in.
+
+use std::thread;
+use std::time::Duration;
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::peer_of;
+
+#[test]
+fn only_the_latest_message_is_taken() {
+    let frames = b"\x00\x011\x01\x012\x00\x012\x00\x013";
+
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::Conflate(true))
+        .from_stream(peer_of("PUSH", 1, frames))
+        .unwrap();
+
+    assert_eq!(pull.recv().unwrap(), vec![b"3".to_vec()]);
+}
+
+#[test]
+fn only_the_latest_matching_message_is_taken() {
+    let frames = b"\x00\x02a1\x00\x02a2\x00\x02b3";
+
+    let mut sub: Sub = zedmq::socket()
+        .option(SocketOption::Conflate(true))
+        .from_stream(peer_of("PUB", 1, frames))
+        .unwrap();
+    sub.subscribe(b"a").unwrap();
+
+    assert_eq!(sub.recv().unwrap(), vec![b"a2".to_vec()]);
+}
+
+#[test]
+fn slow_receivers_catch_up() {
+    let ctx = zmq::Context::new();
+    let push = ctx.socket(zmq::PUSH).unwrap();
+    push.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = push.get_last_endpoint().unwrap().unwrap();
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::Conflate(true))
+        .connect(&endpoint)
+        .unwrap();
+
+    for n in 0..100 {
+        push.send(&n.to_string(), 0).unwrap();
+    }
+
+    // Give all of them the time to come in.
+    thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(pull.recv().unwrap(), vec![b"99".to_vec()]);
+}
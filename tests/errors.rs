@@ -0,0 +1,90 @@
+//! ERROR commands end the connection, whichever side sends them.
+
+use std::io;
+
+use zedmq::{prelude::*, PeerError, ReconnectPolicy};
+
+mod common;
+
+use common::{peer, ready};
+
+fn reason(err: &io::Error) -> Option<&str> {
+    PeerError::from_io(err).map(PeerError::reason)
+}
+
+#[test]
+fn error_instead_of_ready() {
+    let conn = peer(b"\x04\x10\x05ERROR\x09forbidden");
+
+    let err = zedmq::from_stream::<Pull, _>(conn).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+    assert_eq!(reason(&err), Some("forbidden"));
+}
+
+#[test]
+fn error_after_ready() {
+    let mut frames = ready("PUSH");
+    frames.extend_from_slice(b"\x00\x02ok");
+    frames.extend_from_slice(b"\x04\x0F\x05ERROR\x08shutdown");
+
+    let conn = peer(&frames);
+    let mut pull: Pull = zedmq::from_stream(conn).unwrap();
+
+    assert_eq!(pull.recv().unwrap(), vec![b"ok".to_vec()]);
+    assert_eq!(reason(&pull.recv().unwrap_err()), Some("shutdown"));
+}
+
+#[test]
+fn error_a_reconnect_gave_up_on() {
+    let mut frames = ready("PUSH");
+    frames.extend_from_slice(b"\x00\x02ok");
+
+    let policy = ReconnectPolicy::default().stop_on_handshake_failure(true);
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::Reconnect(policy))
+        .from_stream_with(peer(&frames), || {
+            Ok(peer(b"\x04\x10\x05ERROR\x09forbidden"))
+        })
+        .unwrap();
+
+    assert_eq!(pull.recv().unwrap(), vec![b"ok".to_vec()]);
+
+    // The peer hangs up and turns us away when reconnecting.
+    let err = pull.recv().unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+    assert_eq!(reason(&err), Some("forbidden"));
+    assert!(err
+        .to_string()
+        .starts_with("gave up connecting after 1 attempt(s)"));
+}
+
+#[test]
+fn incompatible_peers_are_told_why() {
+    let conn = peer(&ready("PUB"));
+    let written = conn.written();
+
+    let err = zedmq::from_stream::<Pull, _>(conn).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let error = b"\x04\x1A\x05ERROR\x13invalid socket type";
+    assert!(written.lock().unwrap().ends_with(error));
+}
+
+#[test]
+fn libzmq_refuses_incompatible_peers() {
+    let ctx = zmq::Context::new();
+    let pull = ctx.socket(zmq::PULL).unwrap();
+    pull.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = pull.get_last_endpoint().unwrap().unwrap();
+
+    let result = zedmq::socket::<Pull>()
+        .option(SocketOption::Reconnect(
+            ReconnectPolicy::default().stop_on_handshake_failure(true),
+        ))
+        .connect(&endpoint);
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,148 @@
+//! Framed sockets end their stream when the peer hangs up, unless it leaves something cut short,
+//! only read as much as it takes to receive a message and take the same options as every other
+//! socket.
+
+#![cfg(feature = "futures")]
+
+use std::io;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use futures::{io::AllowStdIo, StreamExt};
+use zedmq::framed::{Pull, Sub};
+use zedmq::SocketOption;
+
+mod common;
+
+use common::{greeting, pub_peer, push_peer, ready};
+
+#[tokio::test]
+async fn hanging_up_in_between_messages_ends_the_stream() {
+    let mut pull = Pull::new(AllowStdIo::new(push_peer(b"\x00\x02ok")))
+        .await
+        .unwrap();
+
+    assert_eq!(pull.next().await.unwrap().unwrap(), vec![b"ok".to_vec()]);
+    assert!(pull.next().await.is_none());
+}
+
+#[tokio::test]
+async fn hanging_up_mid_frame_is_an_error() {
+    // The flags of the next frame come in but its size never does.
+    let mut pull = Pull::new(AllowStdIo::new(push_peer(b"\x00\x02ok\x00")))
+        .await
+        .unwrap();
+
+    assert_eq!(pull.next().await.unwrap().unwrap(), vec![b"ok".to_vec()]);
+
+    let err = pull.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[tokio::test]
+async fn messages_past_the_one_received_wait_with_the_peer() {
+    // Every message takes a few reads, so reading ahead would take in the next ones whole.
+    const PART: usize = 1 << 18;
+
+    let mut frames = vec![];
+
+    for n in 0..16u8 {
+        frames.push(0x2);
+        frames.extend_from_slice(&(PART as u64).to_be_bytes());
+        frames.resize(frames.len() + PART, n);
+    }
+
+    let peer = push_peer(&frames);
+    let taken = peer.taken();
+
+    let mut pull: Pull<_> = zedmq::socket()
+        .option(SocketOption::RcvHwm(1))
+        .handshake(AllowStdIo::new(peer))
+        .await
+        .unwrap();
+
+    let handshake = greeting(0).len() + ready("PUSH").len();
+
+    for n in 0..16 {
+        assert_eq!(
+            pull.next().await.unwrap().unwrap(),
+            vec![vec![n as u8; PART]]
+        );
+
+        // No more than a read's worth of the next message is taken in.
+        let received = handshake + (n + 1) * (9 + PART);
+        assert!(taken.load(Ordering::SeqCst) <= received + (64 << 10));
+    }
+}
+
+#[tokio::test]
+async fn options_are_set_through_the_builder() {
+    let frames = b"\x00\x011\x00\x012\x00\x013";
+
+    let mut pull: Pull<_> = zedmq::socket()
+        .option(SocketOption::Conflate(true))
+        .handshake(AllowStdIo::new(push_peer(frames)))
+        .await
+        .unwrap();
+
+    assert!(pull.options().conflate());
+    assert_eq!(pull.next().await.unwrap().unwrap(), vec![b"3".to_vec()]);
+}
+
+#[tokio::test]
+async fn conflating_reads_ahead_for_the_latest_message() {
+    // More than the first read takes in, so the latest message has to be read ahead for.
+    let mut frames = vec![];
+
+    for n in 0..4u8 {
+        frames.extend_from_slice(&[0x2, 0, 0, 0, 0, 0, 0, 0xA0, 0]);
+        frames.resize(frames.len() + 0xA000, n);
+    }
+
+    let mut pull: Pull<_> = zedmq::socket()
+        .option(SocketOption::Conflate(true))
+        .handshake(AllowStdIo::new(push_peer(&frames)))
+        .await
+        .unwrap();
+
+    assert_eq!(pull.next().await.unwrap().unwrap(), vec![vec![3; 0xA000]]);
+}
+
+#[tokio::test]
+async fn oversized_messages_are_refused() {
+    let mut frames = vec![0x0, 100];
+    frames.resize(102, 0);
+
+    let mut pull: Pull<_> = zedmq::socket()
+        .option(SocketOption::MaxMsgSize(Some(64)))
+        .handshake(AllowStdIo::new(push_peer(&frames)))
+        .await
+        .unwrap();
+
+    let err = pull.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn matching_can_be_inverted() {
+    let frames = b"\x00\x02a1\x00\x02b2";
+
+    let mut sub: Sub<_> = zedmq::socket()
+        .option(SocketOption::InvertMatching(true))
+        .handshake(AllowStdIo::new(pub_peer(1, frames)))
+        .await
+        .unwrap();
+    sub.subscribe(b"a").await.unwrap();
+
+    assert_eq!(sub.next().await.unwrap().unwrap(), vec![b"b2".to_vec()]);
+}
+
+#[tokio::test]
+async fn heartbeats_are_refused() {
+    let result: io::Result<Pull<_>> = zedmq::socket()
+        .option(SocketOption::HeartbeatIvl(Some(Duration::from_secs(1))))
+        .handshake(AllowStdIo::new(push_peer(b"")))
+        .await;
+
+    assert_eq!(result.err().unwrap().kind(), io::ErrorKind::Unsupported);
+}
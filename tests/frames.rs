@@ -0,0 +1,71 @@
+//! Round-trips messages of awkward sizes between zedmq and libzmq.
+
+use zedmq::prelude::*;
+
+/// Frame sizes on either side of the short/long encoding boundary.
+const SIZES: &[usize] = &[0, 1, 255, 256, 65_536];
+
+/// Bind a libzmq socket on an ephemeral port and return it along with its address.
+fn bind(ctx: &zmq::Context, kind: zmq::SocketType) -> (zmq::Socket, String) {
+    let socket = ctx.socket(kind).unwrap();
+    socket.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = socket.get_last_endpoint().unwrap().unwrap();
+    let address = endpoint.trim_start_matches("tcp://").to_string();
+
+    (socket, address)
+}
+
+/// Every size as a single frame message, then all of them as one multipart message.
+fn messages(sizes: &[usize]) -> Vec<Vec<Vec<u8>>> {
+    let part = |size: usize| (0..size).map(|n| n as u8).collect::<Vec<u8>>();
+
+    let mut messages: Vec<Vec<Vec<u8>>> = sizes.iter().map(|size| vec![part(*size)]).collect();
+    messages.push(sizes.iter().map(|size| part(*size)).collect());
+    messages.push(sizes.iter().rev().map(|size| part(*size)).collect());
+    messages
+}
+
+fn send_to_libzmq(sizes: &[usize]) {
+    let ctx = zmq::Context::new();
+    let (pull, address) = bind(&ctx, zmq::PULL);
+    let mut push: Push = zedmq::connect("tcp", &address).unwrap();
+
+    for message in messages(sizes) {
+        push.send(message.clone()).unwrap();
+        assert_eq!(pull.recv_multipart(0).unwrap(), message);
+    }
+}
+
+fn recv_from_libzmq(sizes: &[usize]) {
+    let ctx = zmq::Context::new();
+    let (push, address) = bind(&ctx, zmq::PUSH);
+    let mut pull: Pull = zedmq::connect("tcp", &address).unwrap();
+
+    for message in messages(sizes) {
+        push.send_multipart(&message, 0).unwrap();
+        assert_eq!(pull.recv().unwrap(), message);
+    }
+}
+
+#[test]
+fn frames_sent_to_libzmq() {
+    send_to_libzmq(SIZES);
+}
+
+#[test]
+fn frames_received_from_libzmq() {
+    recv_from_libzmq(SIZES);
+}
+
+#[test]
+#[ignore = "needs well over 8 GiB of memory"]
+fn huge_frames_sent_to_libzmq() {
+    send_to_libzmq(&[(4 << 30) + 1]);
+}
+
+#[test]
+#[ignore = "needs well over 8 GiB of memory"]
+fn huge_frames_received_from_libzmq() {
+    recv_from_libzmq(&[(4 << 30) + 1]);
+}
@@ -0,0 +1,141 @@
+//! Sockets queue messages up to their high-water marks, then block or drop depending on their type,
+//! while messages from a peer are left with it until they're received.
+
+use std::io;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::{bound, greeting, push_peer, ready, SIZE};
+
+/// Receive messages, `pause`ing after each, until none come in for a while.
+///
+/// Every message has to arrive whole.
+fn received(socket: zmq::Socket, pause: Duration) -> Vec<u8> {
+    socket.set_rcvtimeo(1000).unwrap();
+
+    let mut received = vec![];
+
+    while let Ok(message) = socket.recv_bytes(0) {
+        assert_eq!(message.len(), SIZE);
+        assert!(message.iter().all(|byte| *byte == message[0]));
+        received.push(message[0]);
+
+        thread::sleep(pause);
+    }
+
+    received
+}
+
+#[test]
+fn push_blocks_at_the_sndhwm() {
+    let ctx = zmq::Context::new();
+    let (pull, endpoint) = bound(&ctx, zmq::PULL);
+
+    let mut push: Push = zedmq::socket()
+        .option(SocketOption::SndHwm(1))
+        .option(SocketOption::SndTimeo(Some(Duration::from_millis(2))))
+        .option(SocketOption::Linger(Some(Duration::from_secs(10))))
+        .connect(&endpoint)
+        .unwrap();
+
+    // A slow receiver lets sends get part of the queue out before timing out.
+    let receiver = thread::spawn(move || received(pull, Duration::from_millis(2)));
+
+    let mut sent = vec![];
+
+    for n in 0..64u8 {
+        match push.send(vec![n; SIZE]) {
+            Ok(()) => sent.push(n),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::WouldBlock),
+        }
+    }
+
+    assert!(sent.len() < 64, "sends never blocked");
+
+    push.close().unwrap();
+
+    // None of the messages that made it in were lost or mangled by the timeouts.
+    assert_eq!(receiver.join().unwrap(), sent);
+}
+
+#[test]
+fn pub_drops_at_the_sndhwm() {
+    let ctx = zmq::Context::new();
+    let sub = ctx.socket(zmq::SUB).unwrap();
+    sub.set_rcvhwm(1).unwrap();
+    sub.set_rcvbuf(4096).unwrap();
+    sub.set_subscribe(b"").unwrap();
+
+    let mut publisher: Pub = zedmq::socket()
+        .option(SocketOption::SndHwm(1))
+        .option(SocketOption::SndTimeo(Some(Duration::from_millis(20))))
+        .bind("tcp://127.0.0.1:0")
+        .unwrap();
+
+    sub.connect(&format!("tcp://{}", publisher.local_addr().unwrap()))
+        .unwrap();
+
+    // Wait for the subscription to go through.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    sub.set_rcvtimeo(10).unwrap();
+
+    loop {
+        assert!(Instant::now() < deadline, "never subscribed");
+        publisher.send(vec![0; SIZE]).unwrap();
+
+        if sub.recv_bytes(0).is_ok() {
+            break;
+        }
+    }
+
+    // Nobody is receiving, none of these block but most of them are dropped.
+    let started = Instant::now();
+
+    for n in 1..=32u8 {
+        publisher.send(vec![n; SIZE]).unwrap();
+    }
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let received = received(sub, Duration::ZERO);
+
+    assert!(received.len() < 32, "nothing was dropped");
+    assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn messages_past_the_one_received_wait_with_the_peer() {
+    // Every message takes a few reads, so reading ahead would take in the next ones whole.
+    const PART: usize = 1 << 18;
+
+    let mut frames = vec![];
+
+    for n in 0..16u8 {
+        frames.push(0x2);
+        frames.extend_from_slice(&(PART as u64).to_be_bytes());
+        frames.resize(frames.len() + PART, n);
+    }
+
+    let peer = push_peer(&frames);
+    let taken = peer.taken();
+
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::RcvHwm(1))
+        .from_stream(peer)
+        .unwrap();
+
+    let handshake = greeting(0).len() + ready("PUSH").len();
+
+    for n in 0..16 {
+        assert_eq!(pull.recv().unwrap(), vec![vec![n as u8; PART]]);
+
+        // No more than a read's worth of the next message is taken in.
+        let received = handshake + (n + 1) * (9 + PART);
+        assert!(taken.load(Ordering::SeqCst) <= received + (64 << 10));
+    }
+}
@@ -0,0 +1,114 @@
+//! Closing or dropping a socket flushes its queued messages for up to the linger period.
+
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::{bound, greeting, ready, SIZE};
+
+/// A PUSH socket with more queued up than `endpoint` takes without anyone receiving.
+fn backed_up(endpoint: &str, linger: Option<Duration>) -> Push {
+    let mut push: Push = zedmq::socket()
+        .option(SocketOption::Linger(linger))
+        .connect(endpoint)
+        .unwrap();
+
+    for n in 0..4u8 {
+        push.send(vec![n; SIZE]).unwrap();
+    }
+
+    push
+}
+
+#[test]
+fn close_flushes_queued_messages() {
+    let ctx = zmq::Context::new();
+    let (pull, endpoint) = bound(&ctx, zmq::PULL);
+
+    let receiver = thread::spawn(move || {
+        (0..4u8)
+            .map(|_| pull.recv_bytes(0).unwrap()[0])
+            .collect::<Vec<_>>()
+    });
+
+    backed_up(&endpoint, None).close().unwrap();
+
+    assert_eq!(receiver.join().unwrap(), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn close_gives_up_after_the_linger_period() {
+    let ctx = zmq::Context::new();
+    let (_pull, endpoint) = bound(&ctx, zmq::PULL);
+
+    let push = backed_up(&endpoint, Some(Duration::from_millis(100)));
+
+    let started = Instant::now();
+    let err = push.close().unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn no_linger_discards_queued_messages() {
+    let ctx = zmq::Context::new();
+    let (_pull, endpoint) = bound(&ctx, zmq::PULL);
+
+    let push = backed_up(&endpoint, Some(Duration::ZERO));
+
+    let started = Instant::now();
+    let err = push.close().unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn drop_lingers_for_the_linger_period() {
+    let ctx = zmq::Context::new();
+    let (_pull, endpoint) = bound(&ctx, zmq::PULL);
+
+    let push = backed_up(&endpoint, Some(Duration::from_millis(100)));
+
+    let started = Instant::now();
+    drop(push);
+
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn drop_does_not_wait_on_peers_that_never_hang_up() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("tcp://{}", listener.local_addr().unwrap());
+
+    // A PUB peer that holds on to the connection for good.
+    thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.write_all(&greeting(1)).unwrap();
+        conn.write_all(&ready("PUB")).unwrap();
+
+        thread::sleep(Duration::from_secs(60));
+    });
+
+    let (dropped, done) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut sub: Sub = zedmq::socket().connect(&endpoint).unwrap();
+        sub.subscribe(b"").unwrap();
+
+        // Nothing is queued up, so there's nothing to linger on.
+        drop(sub);
+        dropped.send(()).unwrap();
+    });
+
+    done.recv_timeout(Duration::from_secs(5)).unwrap();
+}
@@ -0,0 +1,98 @@
+//! Messages over `ZMQ_MAXMSGSIZE` are refused before any room is made for them, and
+//! without one room is only made for what the peer actually sent.
+
+use std::io;
+use std::time::Duration;
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::push_peer;
+
+#[test]
+fn forged_frame_sizes_are_refused() {
+    let mut frames = vec![0x0, 2, b'o', b'k', 0x2];
+    frames.extend_from_slice(&(1u64 << 62).to_be_bytes());
+
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::MaxMsgSize(Some(1024)))
+        .from_stream(push_peer(&frames))
+        .unwrap();
+
+    assert_eq!(pull.recv().unwrap(), vec![b"ok".to_vec()]);
+    assert_eq!(pull.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn forged_frame_sizes_reserve_nothing_without_a_max() {
+    for size in [1u64 << 40, u64::MAX - 100] {
+        let mut frames = vec![0x2];
+        frames.extend_from_slice(&size.to_be_bytes());
+        frames.extend_from_slice(b"not nearly enough");
+
+        let mut pull: Pull = zedmq::from_stream(push_peer(&frames)).unwrap();
+
+        // The peer hangs up long before sending that much, which is all that happens.
+        assert!(pull.recv().is_err());
+    }
+}
+
+#[test]
+fn multipart_sizes_add_up() {
+    let mut frames = vec![];
+
+    for _ in 0..3 {
+        frames.extend_from_slice(&[0x1, 200]);
+        frames.extend_from_slice(&[0; 200]);
+    }
+
+    frames.extend_from_slice(&[0x0, 0]);
+
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::MaxMsgSize(Some(500)))
+        .from_stream(push_peer(&frames))
+        .unwrap();
+
+    assert_eq!(pull.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn oversized_messages_drop_the_connection() {
+    let ctx = zmq::Context::new();
+    let push = ctx.socket(zmq::PUSH).unwrap();
+    push.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = push.get_last_endpoint().unwrap().unwrap();
+
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::MaxMsgSize(Some(1024)))
+        .option(SocketOption::RcvTimeo(Some(Duration::from_millis(100))))
+        .connect(&endpoint)
+        .unwrap();
+
+    push.send(&[1; 1024][..], 0).unwrap();
+    assert_eq!(pull.recv().unwrap()[0].len(), 1024);
+
+    push.send(&[2; 1025][..], 0).unwrap();
+    assert_eq!(pull.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // The next receive reconnects, until libzmq notices messages go to the old connection.
+    let message = loop {
+        let _ = push.send("small", zmq::DONTWAIT);
+
+        match pull.recv() {
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            message => break message.unwrap(),
+        }
+    };
+
+    assert_eq!(message, vec![b"small".to_vec()]);
+}
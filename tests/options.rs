@@ -0,0 +1,72 @@
+//! Options and endpoints are checked when a socket is built, not when it's first used.
+
+use std::io;
+use std::time::Duration;
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::push_peer;
+
+#[test]
+fn unknown_transports_are_invalid() {
+    let err = zedmq::socket::<Pull>()
+        .connect("udp://127.0.0.1:5678")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let err = zedmq::connect::<Push, _>("udp", "127.0.0.1:5678").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn ipc_is_unsupported() {
+    let err = zedmq::socket::<Pull>()
+        .connect("ipc:///tmp/zedmq.ipc")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+    let err = zedmq::socket::<Pub>()
+        .bind("ipc:///tmp/zedmq.ipc")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn endpoints_need_a_transport() {
+    let err = zedmq::socket::<Pull>()
+        .connect("127.0.0.1:5678")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn heartbeats_are_unsupported() {
+    let options = [
+        SocketOption::HeartbeatIvl(Some(Duration::from_secs(1))),
+        SocketOption::HeartbeatTtl(Some(Duration::from_secs(1))),
+        SocketOption::HeartbeatTimeout(Some(Duration::from_secs(1))),
+    ];
+
+    for option in options {
+        let err = zedmq::socket::<Pull>()
+            .option(option.clone())
+            .from_stream(push_peer(b""))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+        // Nothing is even attempted, nobody listens there.
+        let err = zedmq::socket::<Pull>()
+            .option(option)
+            .connect("tcp://127.0.0.1:1")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    let pull: Pull = zedmq::socket()
+        .option(SocketOption::HeartbeatIvl(None))
+        .from_stream(push_peer(b""))
+        .unwrap();
+    assert_eq!(pull.options().heartbeat_ivl(), None);
+}
@@ -0,0 +1,111 @@
+//! Sends cope with connections that only take a few bytes at a time, and small frames go out together.
+
+use std::io::{self, IoSlice, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::{pull_peer, Playback};
+
+/// A connection that takes at most 3 bytes per write, and gets interrupted every third one.
+struct Trickle {
+    peer: Playback,
+    writes: usize,
+}
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.peer.read(buf)
+    }
+}
+
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+
+        if self.writes.is_multiple_of(3) {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+
+        self.peer.write(&buf[..buf.len().min(3)])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.peer.flush()
+    }
+}
+
+/// A connection that takes everything it's given, counting the writes.
+struct Counted {
+    peer: Playback,
+    writes: Arc<AtomicUsize>,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.peer.read(buf)
+    }
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        bufs.iter().map(|buf| self.peer.write(buf)).sum()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.peer.flush()
+    }
+}
+
+fn messages() -> Vec<MessageBuf> {
+    vec![
+        ("a", vec![b'b'; 300], "c").into(),
+        "hello".into(),
+        vec![vec![], vec![b'd'; 70_000]].into(),
+    ]
+}
+
+#[test]
+fn messages_survive_partial_writes() {
+    let expected = pull_peer(b"");
+    let written = expected.written();
+    let mut push: Push = zedmq::from_stream(expected).unwrap();
+
+    let trickle = pull_peer(b"");
+    let trickled = trickle.written();
+    let mut slow: Push = zedmq::from_stream(Trickle {
+        peer: trickle,
+        writes: 0,
+    })
+    .unwrap();
+
+    for message in messages() {
+        push.send(message.clone()).unwrap();
+        slow.send(message).unwrap();
+    }
+
+    assert!(*trickled.lock().unwrap() == *written.lock().unwrap());
+}
+
+#[test]
+fn small_frames_go_out_in_one_write() {
+    let writes = Arc::new(AtomicUsize::new(0));
+    let mut push: Push = zedmq::from_stream(Counted {
+        peer: pull_peer(b""),
+        writes: writes.clone(),
+    })
+    .unwrap();
+
+    let before = writes.load(Ordering::SeqCst);
+    push.send(("a", "b", "c", "d")).unwrap();
+
+    assert_eq!(writes.load(Ordering::SeqCst) - before, 1);
+}
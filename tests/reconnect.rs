@@ -0,0 +1,113 @@
+//! Reconnect policies, driven through a connection factory that never succeeds.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use zedmq::{prelude::*, ReconnectPolicy};
+
+mod common;
+
+use common::push_peer;
+
+/// Receive from a peer that hangs up straight away and can't be reached again.
+///
+/// Returns the error the receive gave up with and when every attempt to reconnect was made.
+fn reconnecting(policy: ReconnectPolicy) -> (io::Error, Vec<Instant>) {
+    let attempts = Arc::new(Mutex::new(vec![]));
+    let noted = attempts.clone();
+
+    let mut pull: Pull = zedmq::socket()
+        .option(SocketOption::Reconnect(policy))
+        .from_stream_with(push_peer(b""), move || {
+            noted.lock().unwrap().push(Instant::now());
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+        })
+        .unwrap();
+
+    let err = pull.recv().unwrap_err();
+    let attempts = attempts.lock().unwrap().clone();
+
+    (err, attempts)
+}
+
+/// The time in between consecutive attempts.
+fn gaps(attempts: &[Instant]) -> Vec<Duration> {
+    attempts.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let policy = ReconnectPolicy::default()
+        .interval(Duration::from_millis(1))
+        .max_attempts(3);
+
+    let (err, attempts) = reconnecting(policy);
+
+    assert_eq!(attempts.len(), 3);
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+}
+
+#[test]
+fn stops_on_refused() {
+    let policy = ReconnectPolicy::default()
+        .interval(Duration::from_millis(1))
+        .max_attempts(10)
+        .stop_on_refused(true);
+
+    let (_, attempts) = reconnecting(policy);
+
+    assert_eq!(attempts.len(), 1);
+}
+
+#[test]
+fn backs_off_up_to_the_max_interval() {
+    let policy = ReconnectPolicy::default()
+        .interval(Duration::from_millis(20))
+        .interval_max(Duration::from_millis(40))
+        .max_attempts(5);
+
+    let (_, attempts) = reconnecting(policy);
+    let gaps = gaps(&attempts);
+
+    let expected = [20, 40, 40, 40].map(Duration::from_millis);
+
+    for (gap, expected) in gaps.iter().zip(expected) {
+        assert!(*gap >= expected, "{:?} < {:?}", gap, expected);
+    }
+
+    // Without the cap the last wait would have been 160 ms.
+    assert!(gaps[3] < Duration::from_millis(120), "{:?}", gaps);
+}
+
+#[test]
+fn without_backoff_the_interval_stays_put() {
+    let policy = ReconnectPolicy::default()
+        .interval(Duration::from_millis(20))
+        .max_attempts(4);
+
+    let (_, attempts) = reconnecting(policy);
+
+    for gap in gaps(&attempts) {
+        assert!(gap >= Duration::from_millis(20), "{:?}", gap);
+        assert!(gap < Duration::from_millis(60), "{:?}", gap);
+    }
+}
+
+#[test]
+fn jitter_is_added_on_top() {
+    let policy = ReconnectPolicy::default()
+        .interval(Duration::from_millis(0))
+        .jitter(Duration::from_millis(50))
+        .max_attempts(9);
+
+    let (_, attempts) = reconnecting(policy);
+    let gaps = gaps(&attempts);
+
+    // Eight waits of 25 ms on average, all of them short would take quite the coincidence.
+    assert!(gaps.iter().sum::<Duration>() > Duration::from_millis(20));
+
+    for gap in gaps {
+        assert!(gap < Duration::from_millis(100), "{:?}", gap);
+    }
+}
@@ -0,0 +1,164 @@
+//! Subscriptions go out as commands to ZMTP 3.1 peers and as messages to 3.0 peers.
+
+use std::io;
+
+use zedmq::prelude::*;
+
+mod common;
+
+use common::pub_peer;
+
+#[test]
+fn commands_for_zmtp_3_1() {
+    let conn = pub_peer(1, b"");
+    let written = conn.written();
+    let mut sub: Sub = zedmq::from_stream(conn).unwrap();
+
+    sub.subscribe(b"t").unwrap();
+    assert!(written.lock().unwrap().ends_with(b"\x04\x0B\x09SUBSCRIBEt"));
+
+    sub.unsubscribe(b"t").unwrap();
+    assert!(written.lock().unwrap().ends_with(b"\x04\x08\x06CANCELt"));
+}
+
+#[test]
+fn messages_for_zmtp_3_0() {
+    let conn = pub_peer(0, b"");
+    let written = conn.written();
+    let mut sub: Sub = zedmq::from_stream(conn).unwrap();
+
+    sub.subscribe(b"t").unwrap();
+    assert!(written.lock().unwrap().ends_with(b"\x00\x02\x01t"));
+
+    sub.unsubscribe(b"t").unwrap();
+    assert!(written.lock().unwrap().ends_with(b"\x00\x02\x00t"));
+}
+
+#[test]
+fn long_topics_match_exactly() {
+    let long = vec![b'x'; 300];
+
+    let mut frames = b"\x00\x02lo".to_vec();
+    frames.extend_from_slice(b"\x00\x0Dlonger-topoc!");
+    frames.extend_from_slice(b"\x00\x0Dlonger-topic!");
+    frames.extend_from_slice(b"\x02\x00\x00\x00\x00\x00\x00\x01\x2D");
+    frames.extend_from_slice(&long);
+    frames.push(b'!');
+
+    let conn = pub_peer(1, &frames);
+    let mut sub: Sub = zedmq::from_stream(conn).unwrap();
+
+    sub.subscribe(b"longer-topic").unwrap();
+    sub.subscribe(&long).unwrap();
+
+    assert_eq!(sub.recv().unwrap(), vec![b"longer-topic!".to_vec()]);
+    assert_eq!(sub.recv().unwrap(), vec![[&long[..], b"!"].concat()]);
+}
+
+#[test]
+fn filtered_by_predicates() {
+    let mut frames = b"\x00\x02a1".to_vec();
+    frames.extend_from_slice(b"\x01\x02a2\x00\x04tail");
+    frames.extend_from_slice(b"\x00\x03b22");
+    frames.extend_from_slice(b"\x00\x03a22");
+
+    let conn = pub_peer(1, &frames);
+    let mut sub: Sub = zedmq::from_stream(conn).unwrap();
+
+    sub.subscribe(b"a").unwrap();
+    sub.filter(|topic| topic.len() > 2);
+
+    assert_eq!(sub.recv().unwrap(), vec![b"a22".to_vec()]);
+}
+
+#[test]
+fn inverted_matching() {
+    let frames = b"\x00\x02a1\x00\x02b1";
+
+    let conn = pub_peer(1, frames);
+    let mut sub: Sub = zedmq::socket()
+        .option(SocketOption::InvertMatching(true))
+        .from_stream(conn)
+        .unwrap();
+
+    sub.subscribe(b"a").unwrap();
+
+    assert_eq!(sub.recv().unwrap(), vec![b"b1".to_vec()]);
+}
+
+#[test]
+fn replayed_after_reconnecting() {
+    let conn = pub_peer(1, b"");
+    let fresh = pub_peer(1, b"\x00\x02tx");
+    let written = fresh.written();
+
+    let mut fresh = Some(fresh);
+    let mut sub: Sub = zedmq::from_stream_with(conn, move || {
+        fresh
+            .take()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::ConnectionRefused))
+    })
+    .unwrap();
+
+    sub.subscribe(b"t").unwrap();
+
+    // The first publisher hangs up, the second one only knows about the subscription through the replay.
+    assert_eq!(sub.recv().unwrap(), vec![b"tx".to_vec()]);
+    assert!(written.lock().unwrap().ends_with(b"\x04\x0B\x09SUBSCRIBEt"));
+}
+
+#[test]
+fn sent_once_when_subscribing_reconnects() {
+    let conn = pub_peer(1, b"\x04\x08\x05ERROR\x02no");
+    let fresh = pub_peer(1, b"");
+    let written = fresh.written();
+
+    let mut fresh = Some(fresh);
+    let mut sub: Sub = zedmq::from_stream_with(conn, move || {
+        fresh
+            .take()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::ConnectionRefused))
+    })
+    .unwrap();
+
+    // The first publisher turns us away, subscribing is what connects to the second.
+    assert!(sub.recv().is_err());
+    sub.subscribe(b"t").unwrap();
+
+    let written = written.lock().unwrap();
+    let subscription = b"\x04\x0B\x09SUBSCRIBEt";
+
+    assert!(written.ends_with(subscription));
+    assert_eq!(
+        written
+            .windows(subscription.len())
+            .filter(|window| window == subscription)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn cancelled_once_the_last_subscription_goes() {
+    let ctx = zmq::Context::new();
+    let xpub = ctx.socket(zmq::XPUB).unwrap();
+    xpub.set_xpub_verbose(true).unwrap();
+    xpub.bind("tcp://127.0.0.1:*").unwrap();
+
+    let endpoint = xpub.get_last_endpoint().unwrap().unwrap();
+    let mut sub: Sub = zedmq::socket().connect(&endpoint).unwrap();
+
+    sub.subscribe(b"a").unwrap();
+    assert_eq!(xpub.recv_bytes(0).unwrap(), b"\x01a");
+
+    // None of these tell the publisher anything.
+    sub.subscribe(b"a").unwrap();
+    sub.unsubscribe(b"a").unwrap();
+    sub.unsubscribe(b"never").unwrap();
+
+    sub.subscribe(b"b").unwrap();
+    assert_eq!(xpub.recv_bytes(0).unwrap(), b"\x01b");
+
+    sub.unsubscribe(b"a").unwrap();
+    assert_eq!(xpub.recv_bytes(0).unwrap(), b"\x00a");
+}